@@ -1,11 +1,12 @@
 use ariadne::models::{
     edge::Edge,
     event::Event,
-    gate::{Condition, Gate},
-    node::{Node, NodeBehavior, NodeId, NodeStatus},
+    gate::{Condition, EvaluationContext, Gate},
+    node::{BehaviorError, FailurePolicy, Node, NodeBehavior, NodeContext, NodeId, NodeStatus},
+    timer::TimerRequest,
     workflow::Workflow,
 };
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -13,7 +14,7 @@ struct BenchCondition(bool);
 
 #[typetag::serde]
 impl Condition for BenchCondition {
-    fn evaluate(&self, _event: &Event) -> bool {
+    fn evaluate(&self, _context: &EvaluationContext) -> bool {
         self.0
     }
 }
@@ -23,11 +24,16 @@ struct BenchBehavior;
 
 #[typetag::serde]
 impl NodeBehavior for BenchBehavior {
-    fn on_activated(&self) -> Option<String> {
-        None
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        Ok(None)
     }
 
-    fn on_completed(&self) {}
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
 }
 
 fn create_linear_workflow(size: usize) -> Workflow {
@@ -36,7 +42,7 @@ fn create_linear_workflow(size: usize) -> Workflow {
     for i in 0..size {
         let edges = if i < size - 1 {
             vec![Edge {
-                target: NodeId(i + 1),
+                target: NodeId::new((i + 1).to_string()),
                 gate: Gate::Single(Box::new(BenchCondition(true))),
             }]
         } else {
@@ -44,7 +50,7 @@ fn create_linear_workflow(size: usize) -> Workflow {
         };
 
         nodes.push(Node {
-            id: NodeId(i),
+            id: NodeId::new(i.to_string()),
             name: format!("Node {}", i),
             status: if i == 0 {
                 NodeStatus::Active
@@ -53,6 +59,14 @@ fn create_linear_workflow(size: usize) -> Workflow {
             },
             edges,
             behavior: Box::new(BenchBehavior),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         });
     }
 
@@ -62,12 +76,18 @@ fn create_linear_workflow(size: usize) -> Workflow {
 fn bench_workflow_processing(c: &mut Criterion) {
     let mut group = c.benchmark_group("workflow_processing");
 
-    for size in [10, 100, 1000].iter() {
+    for size in [10, 100, 1000, 10_000, 50_000].iter() {
         group.bench_function(format!("linear_workflow_{}", size), |b| {
-            b.iter(|| {
-                let mut workflow = create_linear_workflow(*size);
-                workflow.process_event(black_box(&Event::UserActivity));
-            });
+            b.iter_batched(
+                || create_linear_workflow(*size),
+                |mut workflow| {
+                    workflow
+                        .process_event(black_box(&Event::UserActivity))
+                        .unwrap();
+                    workflow
+                },
+                BatchSize::LargeInput,
+            );
         });
     }
 
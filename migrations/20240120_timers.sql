@@ -0,0 +1,16 @@
+CREATE TABLE IF NOT EXISTS timers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    workflow_id UUID NOT NULL REFERENCES workflows(id),
+    user_id UUID NOT NULL REFERENCES users(id),
+    node_id TEXT NOT NULL,
+    timer_id TEXT NOT NULL,
+    fire_at TIMESTAMPTZ NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('pending', 'fired', 'cancelled')),
+    locked_until TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_timers_pending_fire_at ON timers(fire_at) WHERE status = 'pending';
+
+CREATE INDEX IF NOT EXISTS idx_timers_workflow_id ON timers(workflow_id);
@@ -0,0 +1,13 @@
+CREATE TABLE IF NOT EXISTS workflow_transitions (
+    id BIGSERIAL PRIMARY KEY,
+    workflow_id UUID NOT NULL REFERENCES workflows(id),
+    node_id TEXT NOT NULL,
+    from_status TEXT NOT NULL,
+    to_status TEXT NOT NULL,
+    event JSONB NOT NULL,
+    gate JSONB,
+    occurred_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_workflow_transitions_workflow_id ON workflow_transitions(workflow_id);
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS workflow_definitions (
+    name TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    definition JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (name, version)
+);
+
+ALTER TABLE workflows ADD COLUMN IF NOT EXISTS definition_name TEXT;
+ALTER TABLE workflows ADD COLUMN IF NOT EXISTS definition_version INTEGER;
+DO $$
+BEGIN
+    IF NOT EXISTS (
+        SELECT 1 FROM pg_constraint
+        WHERE conname = 'fk_workflows_definition'
+          AND conrelid = 'workflows'::regclass
+    ) THEN
+        ALTER TABLE workflows ADD CONSTRAINT fk_workflows_definition
+            FOREIGN KEY (definition_name, definition_version)
+            REFERENCES workflow_definitions(name, version);
+    END IF;
+END
+$$;
+
+CREATE INDEX IF NOT EXISTS idx_workflows_definition
+    ON workflows(definition_name, definition_version);
@@ -0,0 +1,3 @@
+ALTER TABLE workflows ADD COLUMN IF NOT EXISTS failure_reason TEXT;
+
+ALTER TABLE workflow_transitions ADD COLUMN IF NOT EXISTS error TEXT;
@@ -0,0 +1,15 @@
+CREATE TABLE IF NOT EXISTS node_actions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    workflow_id UUID NOT NULL REFERENCES workflows(id),
+    user_id UUID NOT NULL REFERENCES users(id),
+    node_id TEXT NOT NULL,
+    action_id TEXT NOT NULL,
+    hook TEXT NOT NULL CHECK (hook IN ('activated', 'completed')),
+    status TEXT NOT NULL CHECK (status IN ('pending', 'done')),
+    locked_until TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (workflow_id, action_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_node_actions_pending ON node_actions(created_at) WHERE status = 'pending';
@@ -0,0 +1,7 @@
+ALTER TABLE workflows DROP CONSTRAINT IF EXISTS workflows_status_check;
+ALTER TABLE workflows ADD CONSTRAINT workflows_status_check
+    CHECK (status IN ('active', 'completed', 'failed', 'cancelled'));
+
+ALTER TABLE outbox DROP CONSTRAINT IF EXISTS outbox_status_check;
+ALTER TABLE outbox ADD CONSTRAINT outbox_status_check
+    CHECK (status IN ('pending', 'delivered', 'failed', 'cancelled'));
@@ -0,0 +1,4 @@
+ALTER TABLE workflows ADD COLUMN IF NOT EXISTS parent_workflow_id UUID REFERENCES workflows(id);
+ALTER TABLE workflows ADD COLUMN IF NOT EXISTS parent_node_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_workflows_parent ON workflows(parent_workflow_id);
@@ -0,0 +1,6 @@
+ALTER TABLE workflows ADD COLUMN IF NOT EXISTS correlation_key TEXT;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS correlation_key TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_workflows_correlation_key
+    ON workflows(user_id, correlation_key) WHERE status = 'active';
+CREATE INDEX IF NOT EXISTS idx_events_correlation_key ON events(user_id, correlation_key);
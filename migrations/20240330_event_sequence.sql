@@ -0,0 +1,18 @@
+-- Events saved in one transaction share their CURRENT_TIMESTAMP, so they
+-- are ordered by the sequence they were inserted in instead. Existing
+-- events are numbered in the order they were read back so far.
+CREATE SEQUENCE IF NOT EXISTS events_seq_seq;
+ALTER TABLE events ADD COLUMN IF NOT EXISTS seq BIGINT;
+
+UPDATE events SET seq = ordered.seq
+FROM (SELECT id, row_number() OVER (ORDER BY created_at, id) AS seq FROM events) AS ordered
+WHERE events.id = ordered.id AND events.seq IS NULL;
+
+SELECT setval('events_seq_seq', COALESCE((SELECT MAX(seq) FROM events), 0) + 1, false);
+ALTER SEQUENCE events_seq_seq OWNED BY events.seq;
+ALTER TABLE events
+    ALTER COLUMN seq SET DEFAULT nextval('events_seq_seq'),
+    ALTER COLUMN seq SET NOT NULL,
+    ALTER COLUMN created_at SET DEFAULT clock_timestamp();
+
+CREATE INDEX IF NOT EXISTS idx_events_user_seq ON events(user_id, seq);
@@ -0,0 +1,3 @@
+-- Timers, action results and child notifications are delivered to a single
+-- workflow, so replays must not apply them to the user's other workflows
+ALTER TABLE events ADD COLUMN IF NOT EXISTS workflow_id UUID;
@@ -0,0 +1,5 @@
+-- Entries claimed by a relay, or waiting to retry a failed delivery, are
+-- left alone until next_attempt_at
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT CURRENT_TIMESTAMP;
+
+CREATE INDEX IF NOT EXISTS idx_outbox_due ON outbox(next_attempt_at) WHERE status = 'pending';
@@ -0,0 +1,3 @@
+-- Set on every claim, so a relay whose lease ran out cannot record the
+-- outcome of an entry another relay has claimed since
+ALTER TABLE outbox ADD COLUMN IF NOT EXISTS lease_token UUID;
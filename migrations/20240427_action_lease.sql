@@ -0,0 +1,3 @@
+-- Set on every claim, so a runner whose lease ran out cannot record the
+-- outcome of an action another runner has claimed since
+ALTER TABLE node_actions ADD COLUMN IF NOT EXISTS lease_token UUID;
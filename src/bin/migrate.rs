@@ -1,3 +1,11 @@
+use ariadne::models::workflow::BLOB_MAGIC;
+use ariadne::models::Workflow;
+use sqlx::Row;
+use uuid::Uuid;
+
+/// Rows rewritten per batch when re-encoding legacy workflow blobs.
+const REENCODE_BATCH: i64 = 500;
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
@@ -8,5 +16,46 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     sqlx::migrate!("./migrations").run(&pool).await?;
     println!("Migrations completed successfully!");
 
+    let reencoded = reencode_legacy_workflows(&pool).await?;
+    println!("Re-encoded {} legacy workflow blobs", reencoded);
+
     Ok(())
 }
+
+/// Rewrites workflow blobs still in the unversioned bincode encoding, which
+/// can only be read through the frozen layout of that time.
+/// The row version is left alone; a row saved meanwhile is already in the
+/// current encoding and is skipped.
+async fn reencode_legacy_workflows(pool: &sqlx::PgPool) -> Result<u64, Box<dyn std::error::Error>> {
+    let mut reencoded = 0;
+    loop {
+        let rows = sqlx::query(
+            "SELECT id, version, data FROM workflows
+             WHERE substring(data FROM 1 FOR 4) <> $1
+             LIMIT $2",
+        )
+        .bind(&BLOB_MAGIC[..])
+        .bind(REENCODE_BATCH)
+        .fetch_all(pool)
+        .await?;
+        if rows.is_empty() {
+            return Ok(reencoded);
+        }
+
+        for row in rows {
+            let id: Uuid = row.get("id");
+            let version: i64 = row.get("version");
+            let data: Vec<u8> = row.get("data");
+            let workflow = Workflow::from_bytes(&data)
+                .map_err(|e| format!("workflow {} cannot be decoded: {}", id, e))?;
+            let result =
+                sqlx::query("UPDATE workflows SET data = $1 WHERE id = $2 AND version = $3")
+                    .bind(workflow.to_bytes()?)
+                    .bind(id)
+                    .bind(version)
+                    .execute(pool)
+                    .await?;
+            reencoded += result.rows_affected();
+        }
+    }
+}
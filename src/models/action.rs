@@ -0,0 +1,130 @@
+use super::{
+    gate::{Condition, EvaluationContext},
+    node::NodeId,
+    Event,
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+/// Node hook an action runs on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ActionHook {
+    Activated,
+    Completed,
+}
+
+impl std::fmt::Display for ActionHook {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActionHook::Activated => write!(f, "activated"),
+            ActionHook::Completed => write!(f, "completed"),
+        }
+    }
+}
+
+impl std::str::FromStr for ActionHook {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "activated" => Ok(ActionHook::Activated),
+            "completed" => Ok(ActionHook::Completed),
+            _ => Err(format!("Invalid action hook: {}", s)),
+        }
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{0}")]
+pub struct ActionError(pub String);
+
+impl ActionError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self(reason.into())
+    }
+}
+
+/// What an action is told about the node it runs for. `action_id` is
+/// stable across redeliveries, so actions can use it as an idempotency key.
+#[derive(Debug, Clone)]
+pub struct ActionContext {
+    pub workflow_id: Uuid,
+    pub user_id: Uuid,
+    pub node_id: NodeId,
+    pub action_id: String,
+    pub hook: ActionHook,
+}
+
+/// Side effect that may take a while, such as an HTTP call or an email.
+///
+/// Unlike `NodeBehavior` hooks, actions do not run while an event is being
+/// processed. The engine only records that one is due, `ActionRunner` runs
+/// it in the background and its outcome comes back as an `Event::Action`
+/// that edges can wait on. Actions are delivered at least once.
+#[typetag::serde(tag = "type")]
+#[async_trait::async_trait]
+pub trait NodeAction: Send + Sync + Debug {
+    async fn run(&self, context: &ActionContext) -> Result<serde_json::Value, ActionError>;
+
+    fn runs_on(&self, hook: ActionHook) -> bool {
+        hook == ActionHook::Activated
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_secs(30)
+    }
+}
+
+/// Outcome of an action, delivered back to its workflow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ActionResult {
+    Succeeded(serde_json::Value),
+    Failed(String),
+    TimedOut,
+}
+
+/// Action the engine wants run, drained by the caller and persisted with
+/// the workflow.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ActionRequest {
+    pub action_id: String,
+    pub node_id: NodeId,
+    pub hook: ActionHook,
+}
+
+/// Passes once the action of `node_id` has succeeded.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionSucceeded {
+    pub node_id: NodeId,
+}
+
+#[typetag::serde]
+impl Condition for ActionSucceeded {
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        matches!(
+            context.event,
+            Event::Action { node_id, result: ActionResult::Succeeded(_), .. }
+                if *node_id == self.node_id
+        )
+    }
+}
+
+/// Passes once the action of `node_id` has failed or timed out.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ActionFailed {
+    pub node_id: NodeId,
+}
+
+#[typetag::serde]
+impl Condition for ActionFailed {
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        matches!(
+            context.event,
+            Event::Action { node_id, result: ActionResult::Failed(_) | ActionResult::TimedOut, .. }
+                if *node_id == self.node_id
+        )
+    }
+}
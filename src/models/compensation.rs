@@ -0,0 +1,32 @@
+use super::node::{BehaviorError, NodeContext, NodeId};
+use serde::{Deserialize, Serialize};
+use std::fmt::Debug;
+
+/// Undoes what a completed node did, such as revoking a granted coupon or
+/// releasing reserved stock. Compensations run in reverse completion order
+/// once their workflow fails or is cancelled.
+#[typetag::serde(tag = "type")]
+pub trait Compensation: Send + Sync + Debug {
+    fn compensate(&self, context: &mut NodeContext) -> Result<(), BehaviorError>;
+}
+
+/// Compensation progress of a failed or cancelled workflow, persisted with
+/// it so a compensation waiting to retry resumes after a restart.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CompensationState {
+    /// Nodes still to compensate, next first.
+    pub pending: Vec<NodeId>,
+    pub compensated: Vec<NodeId>,
+    /// Nodes whose compensation gave up, with the last error.
+    pub failed: Vec<(NodeId, String)>,
+    /// Failed attempts of the next pending node.
+    pub attempts: u32,
+    /// Timer that triggers the next attempt while a retry is pending.
+    pub timer_id: Option<String>,
+}
+
+impl CompensationState {
+    pub fn is_finished(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
@@ -0,0 +1,35 @@
+use serde::{Deserialize, Serialize};
+
+/// Application key that ties a workflow instance to the events meant for
+/// it, such as the id of the order the instance follows. Events dispatched
+/// with a key only reach the user's workflows started with the same key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CorrelationKey(pub String);
+
+impl CorrelationKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for CorrelationKey {
+    fn from(key: &str) -> Self {
+        Self(key.to_string())
+    }
+}
+
+impl From<String> for CorrelationKey {
+    fn from(key: String) -> Self {
+        Self(key)
+    }
+}
+
+impl std::fmt::Display for CorrelationKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
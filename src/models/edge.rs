@@ -6,3 +6,18 @@ pub struct Edge {
     pub target: NodeId,
     pub gate: Gate,
 }
+
+/// Edge back to a node upstream, which runs the nodes between them again.
+///
+/// When the gate passes, the node completes and the nodes on the way from
+/// `target` back to it are reset to `NotStarted` before `target` is
+/// activated again. Once `target` has run `max_iterations` times, the
+/// first run included, the edge leads to `exit` instead. Loop-back edges
+/// are kept apart from `edges`, so they do not count as cycles.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LoopBack {
+    pub target: NodeId,
+    pub gate: Gate,
+    pub max_iterations: u32,
+    pub exit: NodeId,
+}
@@ -1,7 +1,140 @@
-use serde::{Deserialize, Serialize};
+use super::{
+    action::ActionResult,
+    gate::{Condition, EvaluationContext},
+    node::NodeId,
+    workflow::WorkflowStatus,
+};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+use uuid::Uuid;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Event {
     UserActivity,
-    Timer { timer_id: String },
+    Timer {
+        timer_id: String,
+    },
+    /// Outcome of a node action run in the background.
+    Action {
+        node_id: NodeId,
+        action_id: String,
+        result: ActionResult,
+    },
+    /// Stops the workflow, see `Workflow::cancel`. Dispatching it cancels
+    /// every active workflow of the user.
+    Cancelled {
+        reason: String,
+    },
+    /// Event defined by the application rather than the engine, such as a
+    /// purchase. Usually built from a `CustomEvent` with `Event::custom`.
+    Custom {
+        event_type: String,
+        payload: serde_json::Value,
+    },
+    /// Sent to a parent workflow when the child started by its sub-workflow
+    /// node `node_id` completes, fails or is cancelled.
+    ChildFinished {
+        node_id: NodeId,
+        child_id: Uuid,
+        status: WorkflowStatus,
+        reason: Option<String>,
+    },
+}
+
+/// Typed application event, carried by the engine as `Event::Custom`.
+pub trait CustomEvent: Serialize + DeserializeOwned {
+    /// Name stored as the event type, unique among custom events.
+    const EVENT_TYPE: &'static str;
+}
+
+impl Event {
+    pub fn custom<T: CustomEvent>(event: &T) -> Result<Self, serde_json::Error> {
+        Ok(Event::Custom {
+            event_type: T::EVENT_TYPE.to_string(),
+            payload: serde_json::to_value(event)?,
+        })
+    }
+
+    /// Decodes the payload if this is a custom event of type `T`, and returns
+    /// `None` for any other event.
+    pub fn as_custom<T: CustomEvent>(&self) -> Option<Result<T, serde_json::Error>> {
+        match self {
+            Event::Custom {
+                event_type,
+                payload,
+            } if event_type == T::EVENT_TYPE => Some(T::deserialize(payload)),
+            _ => None,
+        }
+    }
+}
+
+/// Prefix that keeps custom event types apart from the built-in ones in the
+/// `event_type` column.
+pub const CUSTOM_PREFIX: &str = "custom:";
+
+/// Maps an event to the `event_type` and `event_data` it is stored with.
+pub fn encode_event(event: &Event) -> (String, Value) {
+    match event {
+        Event::UserActivity => ("user_activity".to_string(), Value::Null),
+        Event::Timer { timer_id } => ("timer".to_string(), json!({ "timer_id": timer_id })),
+        Event::Action {
+            node_id,
+            action_id,
+            result,
+        } => (
+            "action".to_string(),
+            json!({
+                "node_id": node_id,
+                "action_id": action_id,
+                "result": result,
+            }),
+        ),
+        Event::Cancelled { reason } => ("cancelled".to_string(), json!({ "reason": reason })),
+        Event::Custom {
+            event_type,
+            payload,
+        } => (format!("{}{}", CUSTOM_PREFIX, event_type), payload.clone()),
+        Event::ChildFinished {
+            node_id,
+            child_id,
+            status,
+            reason,
+        } => (
+            "child_finished".to_string(),
+            json!({
+                "node_id": node_id,
+                "child_id": child_id,
+                "status": status.to_string(),
+                "reason": reason,
+            }),
+        ),
+    }
+}
+
+/// Passes on any custom event of the given type.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CustomEventCondition {
+    pub event_type: String,
+}
+
+impl CustomEventCondition {
+    pub fn new(event_type: impl Into<String>) -> Self {
+        Self {
+            event_type: event_type.into(),
+        }
+    }
+
+    pub fn of<T: CustomEvent>() -> Self {
+        Self::new(T::EVENT_TYPE)
+    }
+}
+
+#[typetag::serde]
+impl Condition for CustomEventCondition {
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        matches!(
+            context.event,
+            Event::Custom { event_type, .. } if *event_type == self.event_type
+        )
+    }
 }
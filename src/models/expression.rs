@@ -0,0 +1,606 @@
+//! A small expression language for gate conditions, so simple predicates do
+//! not each need their own `Condition` type:
+//!
+//! ```text
+//! event.type == "purchase" && event.amount > 100 && vars.tier == "gold"
+//! ```
+//!
+//! `event.type` is the event type (`"user_activity"`, `"timer"`, `"action"`
+//! or the type of a custom event) and the other `event` fields are those of
+//! the event, including the payload fields of custom events. Since a custom
+//! event may share its type with a built-in one, `event.kind` tells them
+//! apart: it is `"custom"` for custom events and the type for the others.
+//! `vars` reads the workflow variables. Missing fields evaluate to `null`.
+//!
+//! Expressions support `&&`, `||`, `!`, `==`, `!=`, `<`, `<=`, `>`, `>=`,
+//! `+`, `-`, `*`, `/`, parentheses, and number, string, `true`, `false` and
+//! `null` literals. They are parsed and type-checked once, when the
+//! condition is built or deserialized, so a malformed expression in a
+//! definition is rejected at load time.
+
+use super::{
+    event::{encode_event, CUSTOM_PREFIX},
+    gate::{Condition, EvaluationContext},
+    Event,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Number, Value};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{message} at column {column}")]
+pub struct ExpressionError {
+    /// 1-based character position in the expression the error refers to.
+    pub column: usize,
+    pub message: String,
+}
+
+impl ExpressionError {
+    fn new(position: usize, message: impl Into<String>) -> Self {
+        Self {
+            column: position + 1,
+            message: message.into(),
+        }
+    }
+}
+
+/// Passes when its expression evaluates to `true`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "ExpressionSource", into = "ExpressionSource")]
+pub struct ExpressionCondition {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExpressionSource {
+    expression: String,
+}
+
+impl ExpressionCondition {
+    pub fn new(expression: impl Into<String>) -> Result<Self, ExpressionError> {
+        let source = expression.into();
+        let expr = Parser::new(&source)?.parse()?;
+        match check(&expr)? {
+            Type::Bool | Type::Any => Ok(Self { source, expr }),
+            other => Err(ExpressionError::new(
+                0,
+                format!("expression must be a boolean, found {}", other.name()),
+            )),
+        }
+    }
+
+    pub fn expression(&self) -> &str {
+        &self.source
+    }
+}
+
+impl TryFrom<ExpressionSource> for ExpressionCondition {
+    type Error = ExpressionError;
+
+    fn try_from(source: ExpressionSource) -> Result<Self, Self::Error> {
+        Self::new(source.expression)
+    }
+}
+
+impl From<ExpressionCondition> for ExpressionSource {
+    fn from(condition: ExpressionCondition) -> Self {
+        Self {
+            expression: condition.source,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Condition for ExpressionCondition {
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        let scope = Scope {
+            event: event_fields(context.event),
+            context,
+        };
+        scope.eval(&self.expr) == Value::Bool(true)
+    }
+}
+
+/// The fields `event.<name>` resolves against: the columns the event is
+/// stored with, so expressions see events the way replay does.
+fn event_fields(event: &Event) -> Value {
+    let (stored_type, data) = encode_event(event);
+    let (event_type, kind) = match stored_type.strip_prefix(CUSTOM_PREFIX) {
+        Some(event_type) => (event_type.to_string(), "custom".to_string()),
+        None => (stored_type.clone(), stored_type),
+    };
+
+    let mut map = match data {
+        Value::Object(map) => map,
+        Value::Null => Map::new(),
+        other => Map::from_iter([("payload".to_string(), other)]),
+    };
+    map.insert("type".to_string(), Value::String(event_type));
+    map.insert("kind".to_string(), Value::String(kind));
+    Value::Object(map)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Root {
+    Event,
+    Vars,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum BinaryOp {
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl BinaryOp {
+    fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Literal(Value),
+    Path(Root, Vec<String>),
+    Not(Box<Expr>, usize),
+    Neg(Box<Expr>, usize),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>, usize),
+}
+
+/// Static type of an expression. Paths are `Any` since event payloads and
+/// variables are only known at evaluation time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Bool,
+    Number,
+    String,
+    Null,
+    Any,
+}
+
+impl Type {
+    fn name(self) -> &'static str {
+        match self {
+            Type::Bool => "boolean",
+            Type::Number => "number",
+            Type::String => "string",
+            Type::Null => "null",
+            Type::Any => "any",
+        }
+    }
+
+    fn is(self, expected: Type) -> bool {
+        self == expected || self == Type::Any
+    }
+}
+
+fn check(expr: &Expr) -> Result<Type, ExpressionError> {
+    match expr {
+        Expr::Literal(value) => Ok(match value {
+            Value::Bool(_) => Type::Bool,
+            Value::Number(_) => Type::Number,
+            Value::String(_) => Type::String,
+            _ => Type::Null,
+        }),
+        Expr::Path(..) => Ok(Type::Any),
+        Expr::Not(operand, position) => match check(operand)? {
+            t if t.is(Type::Bool) => Ok(Type::Bool),
+            t => Err(ExpressionError::new(
+                *position,
+                format!("`!` expects a boolean, found {}", t.name()),
+            )),
+        },
+        Expr::Neg(operand, position) => match check(operand)? {
+            t if t.is(Type::Number) => Ok(Type::Number),
+            t => Err(ExpressionError::new(
+                *position,
+                format!("`-` expects a number, found {}", t.name()),
+            )),
+        },
+        Expr::Binary(op, lhs, rhs, position) => {
+            let (lhs, rhs) = (check(lhs)?, check(rhs)?);
+            let mismatch = || {
+                ExpressionError::new(
+                    *position,
+                    format!(
+                        "`{}` cannot be applied to {} and {}",
+                        op.symbol(),
+                        lhs.name(),
+                        rhs.name()
+                    ),
+                )
+            };
+            let known = lhs != Type::Any && rhs != Type::Any;
+            match op {
+                BinaryOp::And | BinaryOp::Or => {
+                    if lhs.is(Type::Bool) && rhs.is(Type::Bool) {
+                        Ok(Type::Bool)
+                    } else {
+                        Err(mismatch())
+                    }
+                }
+                BinaryOp::Eq | BinaryOp::Ne => {
+                    if known && lhs != rhs && lhs != Type::Null && rhs != Type::Null {
+                        Err(mismatch())
+                    } else {
+                        Ok(Type::Bool)
+                    }
+                }
+                BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+                    let ordered = |t: Type| matches!(t, Type::Number | Type::String | Type::Any);
+                    if ordered(lhs) && ordered(rhs) && !(known && lhs != rhs) {
+                        Ok(Type::Bool)
+                    } else {
+                        Err(mismatch())
+                    }
+                }
+                BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                    if lhs.is(Type::Number) && rhs.is(Type::Number) {
+                        Ok(Type::Number)
+                    } else {
+                        Err(mismatch())
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct Scope<'a> {
+    event: Value,
+    context: &'a EvaluationContext<'a>,
+}
+
+impl Scope<'_> {
+    /// Values of the wrong type at runtime make comparisons false and
+    /// arithmetic `null` rather than failing the event.
+    fn eval(&self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Literal(value) => value.clone(),
+            Expr::Path(root, path) => self.resolve(*root, path),
+            Expr::Not(operand, _) => match self.eval(operand) {
+                Value::Bool(b) => Value::Bool(!b),
+                _ => Value::Null,
+            },
+            Expr::Neg(operand, _) => number(self.eval(operand).as_f64().map(|n| -n)),
+            Expr::Binary(BinaryOp::And, lhs, rhs, _) => Value::Bool(
+                self.eval(lhs) == Value::Bool(true) && self.eval(rhs) == Value::Bool(true),
+            ),
+            Expr::Binary(BinaryOp::Or, lhs, rhs, _) => Value::Bool(
+                self.eval(lhs) == Value::Bool(true) || self.eval(rhs) == Value::Bool(true),
+            ),
+            Expr::Binary(op, lhs, rhs, _) => {
+                let (lhs, rhs) = (self.eval(lhs), self.eval(rhs));
+                match op {
+                    BinaryOp::Eq => Value::Bool(equal(&lhs, &rhs)),
+                    BinaryOp::Ne => Value::Bool(!equal(&lhs, &rhs)),
+                    BinaryOp::Lt => Value::Bool(compare(&lhs, &rhs).is_some_and(|o| o.is_lt())),
+                    BinaryOp::Le => Value::Bool(compare(&lhs, &rhs).is_some_and(|o| o.is_le())),
+                    BinaryOp::Gt => Value::Bool(compare(&lhs, &rhs).is_some_and(|o| o.is_gt())),
+                    BinaryOp::Ge => Value::Bool(compare(&lhs, &rhs).is_some_and(|o| o.is_ge())),
+                    BinaryOp::Add => arithmetic(&lhs, &rhs, |a, b| a + b),
+                    BinaryOp::Sub => arithmetic(&lhs, &rhs, |a, b| a - b),
+                    BinaryOp::Mul => arithmetic(&lhs, &rhs, |a, b| a * b),
+                    BinaryOp::Div => arithmetic(&lhs, &rhs, |a, b| a / b),
+                    BinaryOp::And | BinaryOp::Or => unreachable!("handled above"),
+                }
+            }
+        }
+    }
+
+    fn resolve(&self, root: Root, path: &[String]) -> Value {
+        let (first, rest) = path.split_first().expect("paths have a field");
+        let value = match root {
+            Root::Event => self.event.get(first),
+            Root::Vars => self.context.variables.get_value(first),
+        };
+        rest.iter()
+            .try_fold(value, |value, field| value.map(|v| v.get(field)))
+            .flatten()
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+}
+
+fn number(value: Option<f64>) -> Value {
+    value
+        .and_then(Number::from_f64)
+        .map_or(Value::Null, Value::Number)
+}
+
+fn arithmetic(lhs: &Value, rhs: &Value, op: impl Fn(f64, f64) -> f64) -> Value {
+    number(lhs.as_f64().zip(rhs.as_f64()).map(|(a, b)| op(a, b)))
+}
+
+/// Numbers compare by value, so `100` equals `100.0`.
+fn equal(lhs: &Value, rhs: &Value) -> bool {
+    match (lhs.as_f64(), rhs.as_f64()) {
+        (Some(a), Some(b)) => a == b,
+        _ => lhs == rhs,
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<std::cmp::Ordering> {
+    match (lhs, rhs) {
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Str(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(n) => write!(f, "`{}`", n),
+            Token::Str(s) => write!(f, "{:?}", s),
+            Token::Ident(ident) => write!(f, "`{}`", ident),
+            Token::Op(op) => write!(f, "`{}`", op),
+        }
+    }
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn new(source: &str) -> Result<Self, ExpressionError> {
+        Ok(Self {
+            tokens: tokenize(source)?,
+            pos: 0,
+            end: source.chars().count(),
+        })
+    }
+
+    fn parse(mut self) -> Result<Expr, ExpressionError> {
+        let expr = self.or()?;
+        match self.tokens.get(self.pos) {
+            None => Ok(expr),
+            Some((token, position)) => Err(ExpressionError::new(
+                *position,
+                format!("unexpected {}", token),
+            )),
+        }
+    }
+
+    fn position(&self) -> usize {
+        self.tokens.get(self.pos).map_or(self.end, |(_, p)| *p)
+    }
+
+    /// Consumes the next token if it is one of `ops`.
+    fn eat(&mut self, ops: &[&'static str]) -> Option<(&'static str, usize)> {
+        match self.tokens.get(self.pos) {
+            Some((Token::Op(op), position)) if ops.contains(op) => {
+                self.pos += 1;
+                Some((op, *position))
+            }
+            _ => None,
+        }
+    }
+
+    fn binary(
+        &mut self,
+        ops: &[&'static str],
+        next: fn(&mut Self) -> Result<Expr, ExpressionError>,
+    ) -> Result<Expr, ExpressionError> {
+        let mut lhs = next(self)?;
+        while let Some((op, position)) = self.eat(ops) {
+            let op = match op {
+                "&&" => BinaryOp::And,
+                "||" => BinaryOp::Or,
+                "==" => BinaryOp::Eq,
+                "!=" => BinaryOp::Ne,
+                "<" => BinaryOp::Lt,
+                "<=" => BinaryOp::Le,
+                ">" => BinaryOp::Gt,
+                ">=" => BinaryOp::Ge,
+                "+" => BinaryOp::Add,
+                "-" => BinaryOp::Sub,
+                "*" => BinaryOp::Mul,
+                _ => BinaryOp::Div,
+            };
+            let rhs = next(self)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs), position);
+        }
+        Ok(lhs)
+    }
+
+    fn or(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(&["||"], Self::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(&["&&"], Self::equality)
+    }
+
+    fn equality(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(&["==", "!="], Self::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(&["<", "<=", ">", ">="], Self::additive)
+    }
+
+    fn additive(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(&["+", "-"], Self::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, ExpressionError> {
+        self.binary(&["*", "/"], Self::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ExpressionError> {
+        if let Some((_, position)) = self.eat(&["!"]) {
+            return Ok(Expr::Not(Box::new(self.unary()?), position));
+        }
+        if let Some((_, position)) = self.eat(&["-"]) {
+            return Ok(Expr::Neg(Box::new(self.unary()?), position));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, ExpressionError> {
+        let position = self.position();
+        if self.eat(&["("]).is_some() {
+            let expr = self.or()?;
+            if self.eat(&[")"]).is_none() {
+                return Err(ExpressionError::new(self.position(), "expected `)`"));
+            }
+            return Ok(expr);
+        }
+
+        let Some((token, _)) = self.tokens.get(self.pos).cloned() else {
+            return Err(ExpressionError::new(
+                position,
+                "unexpected end of expression",
+            ));
+        };
+        self.pos += 1;
+        match token {
+            Token::Number(n) => Ok(Expr::Literal(number(Some(n)))),
+            Token::Str(s) => Ok(Expr::Literal(Value::String(s))),
+            Token::Ident(ident) => match ident.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                "event" => Ok(Expr::Path(Root::Event, self.fields()?)),
+                "vars" => Ok(Expr::Path(Root::Vars, self.fields()?)),
+                _ => Err(ExpressionError::new(
+                    position,
+                    format!("unknown name `{}`, expected `event` or `vars`", ident),
+                )),
+            },
+            Token::Op(op) => Err(ExpressionError::new(
+                position,
+                format!("unexpected `{}`", op),
+            )),
+        }
+    }
+
+    /// The `.field` chain after `event` or `vars`, which needs at least one.
+    fn fields(&mut self) -> Result<Vec<String>, ExpressionError> {
+        let mut fields = Vec::new();
+        while self.eat(&["."]).is_some() {
+            match self.tokens.get(self.pos) {
+                Some((Token::Ident(field), _)) => {
+                    fields.push(field.clone());
+                    self.pos += 1;
+                }
+                _ => {
+                    return Err(ExpressionError::new(
+                        self.position(),
+                        "expected a field name",
+                    ))
+                }
+            }
+        }
+        if fields.is_empty() {
+            return Err(ExpressionError::new(self.position(), "expected `.`"));
+        }
+        Ok(fields)
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, ExpressionError> {
+    // Longer operators first so `<=` is not read as `<` followed by `=`
+    const OPS: [&str; 16] = [
+        "&&", "||", "==", "!=", "<=", ">=", "<", ">", "!", "+", "-", "*", "/", "(", ")", ".",
+    ];
+
+    // Positions are counted in characters rather than bytes.
+    let column = |byte: usize| source[..byte].chars().count();
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            let number = source[start..end]
+                .parse()
+                .map_err(|_| ExpressionError::new(column(start), "invalid number"))?;
+            tokens.push((Token::Number(number), column(start)));
+        } else if c.is_alphabetic() || c == '_' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if !(c.is_alphanumeric() || c == '_') {
+                    break;
+                }
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            tokens.push((Token::Ident(source[start..end].to_string()), column(start)));
+        } else if c == '"' {
+            chars.next();
+            let mut value = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((i, '\\')) => match chars.next() {
+                        Some((_, '"')) => value.push('"'),
+                        Some((_, '\\')) => value.push('\\'),
+                        Some((_, 'n')) => value.push('\n'),
+                        Some((_, 't')) => value.push('\t'),
+                        _ => return Err(ExpressionError::new(column(i), "invalid escape")),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err(ExpressionError::new(column(start), "unterminated string")),
+                }
+            }
+            tokens.push((Token::Str(value), column(start)));
+        } else {
+            let op = OPS
+                .iter()
+                .find(|op| source[start..].starts_with(**op))
+                .ok_or_else(|| {
+                    ExpressionError::new(column(start), format!("unexpected character `{}`", c))
+                })?;
+            for _ in 0..op.len() {
+                chars.next();
+            }
+            tokens.push((Token::Op(op), column(start)));
+        }
+    }
+    Ok(tokens)
+}
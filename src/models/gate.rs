@@ -1,13 +1,69 @@
 use super::{
     node::{Node, NodeId, NodeStatus},
-    Event,
+    timer::human_duration,
+    variables::Variables,
+    Event, Workflow,
 };
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// What a condition can look at: the event being processed, the workflow's
+/// variables as they stand when the gate is evaluated, the node whose edge
+/// it guards and the time the event is processed at.
+#[derive(Debug, Clone, Copy)]
+pub struct EvaluationContext<'a> {
+    pub event: &'a Event,
+    pub variables: &'a Variables,
+    pub node: Option<&'a Node>,
+    pub now: OffsetDateTime,
+}
+
+impl<'a> EvaluationContext<'a> {
+    pub fn new(event: &'a Event, variables: &'a Variables) -> Self {
+        Self {
+            event,
+            variables,
+            node: None,
+            now: OffsetDateTime::now_utc(),
+        }
+    }
+
+    pub fn with_node(mut self, node: &'a Node) -> Self {
+        self.node = Some(node);
+        self
+    }
+
+    pub fn at(mut self, now: OffsetDateTime) -> Self {
+        self.now = now;
+        self
+    }
+
+    /// Whether `limit` has passed since the node was activated. The node's
+    /// own deadline timer counts as well, so a timer delivered slightly
+    /// early by a skewed clock still passes.
+    fn elapsed(&self, limit: Duration) -> bool {
+        let Some(node) = self.node else {
+            return false;
+        };
+        let timer_fired = matches!(
+            self.event,
+            Event::Timer { timer_id } if *timer_id == deadline_timer_id(&node.id, limit)
+        );
+        timer_fired || node.activated_at.is_some_and(|at| self.now >= at + limit)
+    }
+}
+
+/// Id of the timer that wakes a node up once `limit` has passed since it
+/// was activated.
+pub fn deadline_timer_id(node_id: &NodeId, limit: Duration) -> String {
+    format!("deadline:{}:{}ms", node_id, limit.as_millis())
+}
 
 #[typetag::serde(tag = "type")]
 pub trait Condition: Send + Sync + Debug {
-    fn evaluate(&self, event: &Event) -> bool;
+    fn evaluate(&self, context: &EvaluationContext) -> bool;
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -17,18 +73,148 @@ pub enum Gate {
     Or(Vec<Gate>),
     Not(Box<Gate>),
     WaitForNodes(Vec<NodeId>),
+    /// Passes once enough of the listed nodes have reached the statuses it
+    /// waits for, see `Join`.
+    Join(Join),
+    /// Passes once the given time has passed since the node was activated.
+    /// A timer is scheduled on activation so the edge is taken even if no
+    /// other event arrives.
+    DeadlineElapsed(#[serde(with = "human_duration")] Duration),
+    /// Passes until the given time has passed since the node was activated.
+    /// Combine with a condition to accept an event only within the window.
+    /// Nodes that start out active count as activated when the workflow is
+    /// created.
+    WithinWindow(#[serde(with = "human_duration")] Duration),
 }
 
 impl Gate {
-    pub fn evaluate(&self, nodes: &[Node], event: &Event) -> bool {
+    pub fn evaluate(&self, workflow: &Workflow, context: &EvaluationContext) -> bool {
+        match self {
+            Gate::Single(condition) => condition.evaluate(context),
+            Gate::And(gates) => gates.iter().all(|g| g.evaluate(workflow, context)),
+            Gate::Or(gates) => gates.iter().any(|g| g.evaluate(workflow, context)),
+            Gate::Not(gate) => !gate.evaluate(workflow, context),
+            Gate::WaitForNodes(required_node_ids) => required_node_ids.iter().all(|node_id| {
+                workflow
+                    .node(node_id)
+                    .is_some_and(|node| node.status == NodeStatus::Completed)
+            }),
+            Gate::Join(join) => join.is_satisfied(workflow),
+            Gate::DeadlineElapsed(limit) => context.elapsed(*limit),
+            Gate::WithinWindow(limit) => {
+                context.node.is_some_and(|node| node.activated_at.is_some())
+                    && !context.elapsed(*limit)
+            }
+        }
+    }
+
+    /// Time limits of `DeadlineElapsed` and `WithinWindow` anywhere inside
+    /// this gate, which need a timer once the node is activated.
+    pub fn time_limits(&self) -> Vec<Duration> {
         match self {
-            Gate::Single(condition) => condition.evaluate(event),
-            Gate::And(gates) => gates.iter().all(|g| g.evaluate(nodes, event)),
-            Gate::Or(gates) => gates.iter().any(|g| g.evaluate(nodes, event)),
-            Gate::Not(gate) => !gate.evaluate(nodes, event),
-            Gate::WaitForNodes(required_node_ids) => required_node_ids
-                .iter()
-                .all(|node_id| nodes[node_id.0].status == NodeStatus::Completed),
+            Gate::Single(_) | Gate::WaitForNodes(_) | Gate::Join(_) => Vec::new(),
+            Gate::And(gates) | Gate::Or(gates) => {
+                gates.iter().flat_map(|g| g.time_limits()).collect()
+            }
+            Gate::Not(gate) => gate.time_limits(),
+            Gate::DeadlineElapsed(limit) | Gate::WithinWindow(limit) => vec![*limit],
         }
     }
+
+    /// Nodes referenced by `WaitForNodes` and `Join` anywhere inside this
+    /// gate.
+    pub fn waited_nodes(&self) -> Vec<NodeId> {
+        match self {
+            Gate::Single(_) | Gate::DeadlineElapsed(_) | Gate::WithinWindow(_) => Vec::new(),
+            Gate::And(gates) | Gate::Or(gates) => {
+                gates.iter().flat_map(|g| g.waited_nodes()).collect()
+            }
+            Gate::Not(gate) => gate.waited_nodes(),
+            Gate::WaitForNodes(node_ids) => node_ids.clone(),
+            Gate::Join(join) => join.nodes.clone(),
+        }
+    }
+
+    /// `Join` gates anywhere inside this gate.
+    pub fn joins(&self) -> Vec<&Join> {
+        match self {
+            Gate::Single(_)
+            | Gate::WaitForNodes(_)
+            | Gate::DeadlineElapsed(_)
+            | Gate::WithinWindow(_) => Vec::new(),
+            Gate::And(gates) | Gate::Or(gates) => gates.iter().flat_map(|g| g.joins()).collect(),
+            Gate::Not(gate) => gate.joins(),
+            Gate::Join(join) => vec![join],
+        }
+    }
+}
+
+/// Synchronizes parallel branches: passes once `quorum` of `nodes` have
+/// reached one of `statuses`.
+///
+/// Like `WaitForNodes`, a join sits on an edge of a node that waits for the
+/// other branches, which end without edges of their own. A join with a
+/// quorum of one is a discriminator: it passes on the first branch to
+/// arrive, its node completes and branches arriving later just finish.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Join {
+    pub nodes: Vec<NodeId>,
+    /// How many of `nodes` must have arrived, or all of them if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quorum: Option<usize>,
+    /// Statuses that count as arrived. Add `Failed` to go on once a branch
+    /// has finished either way.
+    #[serde(default = "default_join_statuses")]
+    pub statuses: Vec<NodeStatus>,
+}
+
+fn default_join_statuses() -> Vec<NodeStatus> {
+    vec![NodeStatus::Completed]
+}
+
+impl Join {
+    /// Waits for every node, like `WaitForNodes`.
+    pub fn all(nodes: Vec<NodeId>) -> Self {
+        Self {
+            nodes,
+            quorum: None,
+            statuses: default_join_statuses(),
+        }
+    }
+
+    /// Waits for the first node to arrive.
+    pub fn any(nodes: Vec<NodeId>) -> Self {
+        Self::at_least(1, nodes)
+    }
+
+    /// Waits for `quorum` of the nodes to arrive.
+    pub fn at_least(quorum: usize, nodes: Vec<NodeId>) -> Self {
+        Self {
+            quorum: Some(quorum),
+            ..Self::all(nodes)
+        }
+    }
+
+    pub fn with_statuses(mut self, statuses: Vec<NodeStatus>) -> Self {
+        self.statuses = statuses;
+        self
+    }
+
+    /// Number of nodes that must arrive for the join to pass.
+    pub fn required(&self) -> usize {
+        self.quorum.unwrap_or(self.nodes.len())
+    }
+
+    pub fn is_satisfied(&self, workflow: &Workflow) -> bool {
+        let arrived = self
+            .nodes
+            .iter()
+            .filter(|node_id| {
+                workflow
+                    .node(node_id)
+                    .is_some_and(|node| self.statuses.contains(&node.status))
+            })
+            .count();
+        arrived >= self.required()
+    }
 }
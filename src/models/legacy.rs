@@ -0,0 +1,135 @@
+//! Frozen copy of the workflow layout stored before blobs were versioned.
+//! Bincode is not self-describing, so these rows only decode into exactly
+//! the structs that wrote them. Do not change these types; rows are
+//! converted to the current model once decoded.
+
+use super::{
+    edge::Edge,
+    gate::{Condition, Gate},
+    node::{FailurePolicy, NodeBehavior, NodeId},
+    workflow::WorkflowStatus,
+    Node, NodeStatus, Workflow,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub(crate) struct LegacyWorkflow {
+    id: Uuid,
+    user_id: Uuid,
+    name: String,
+    nodes: Vec<LegacyNode>,
+    status: LegacyWorkflowStatus,
+}
+
+#[derive(Deserialize)]
+struct LegacyNode {
+    id: LegacyNodeId,
+    name: String,
+    status: LegacyNodeStatus,
+    edges: Vec<LegacyEdge>,
+    behavior: Box<dyn NodeBehavior>,
+}
+
+/// Nodes were identified by their position, which becomes their string id.
+#[derive(Deserialize)]
+struct LegacyNodeId(usize);
+
+#[derive(Deserialize)]
+enum LegacyNodeStatus {
+    NotStarted,
+    Active,
+    Completed,
+}
+
+#[derive(Deserialize)]
+struct LegacyEdge {
+    target: LegacyNodeId,
+    gate: LegacyGate,
+}
+
+#[derive(Deserialize)]
+enum LegacyGate {
+    Single(Box<dyn Condition>),
+    And(Vec<LegacyGate>),
+    Or(Vec<LegacyGate>),
+    Not(Box<LegacyGate>),
+    WaitForNodes(Vec<LegacyNodeId>),
+}
+
+#[derive(Deserialize)]
+enum LegacyWorkflowStatus {
+    Active,
+    Completed,
+    Failed,
+}
+
+impl From<LegacyNodeId> for NodeId {
+    fn from(id: LegacyNodeId) -> Self {
+        NodeId::new(id.0.to_string())
+    }
+}
+
+impl From<LegacyGate> for Gate {
+    fn from(gate: LegacyGate) -> Self {
+        match gate {
+            LegacyGate::Single(condition) => Gate::Single(condition),
+            LegacyGate::And(gates) => Gate::And(gates.into_iter().map(Gate::from).collect()),
+            LegacyGate::Or(gates) => Gate::Or(gates.into_iter().map(Gate::from).collect()),
+            LegacyGate::Not(gate) => Gate::Not(Box::new(Gate::from(*gate))),
+            LegacyGate::WaitForNodes(ids) => {
+                Gate::WaitForNodes(ids.into_iter().map(NodeId::from).collect())
+            }
+        }
+    }
+}
+
+impl From<LegacyNode> for Node {
+    fn from(node: LegacyNode) -> Self {
+        Node {
+            id: node.id.into(),
+            name: node.name,
+            status: match node.status {
+                LegacyNodeStatus::NotStarted => NodeStatus::NotStarted,
+                LegacyNodeStatus::Active => NodeStatus::Active,
+                LegacyNodeStatus::Completed => NodeStatus::Completed,
+            },
+            edges: node
+                .edges
+                .into_iter()
+                .map(|edge| Edge {
+                    target: edge.target.into(),
+                    gate: edge.gate.into(),
+                })
+                .collect(),
+            behavior: node.behavior,
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
+        }
+    }
+}
+
+impl From<LegacyWorkflow> for Workflow {
+    fn from(legacy: LegacyWorkflow) -> Self {
+        let mut workflow = Workflow::new(legacy.nodes.into_iter().map(Node::from).collect());
+        workflow.id = legacy.id;
+        workflow.user_id = legacy.user_id;
+        workflow.name = legacy.name;
+        workflow.status = match legacy.status {
+            LegacyWorkflowStatus::Active => WorkflowStatus::Active,
+            LegacyWorkflowStatus::Completed => WorkflowStatus::Completed,
+            LegacyWorkflowStatus::Failed => WorkflowStatus::Failed,
+        };
+        // When the nodes were activated was never recorded.
+        for node in &mut workflow.nodes {
+            node.activated_at = None;
+        }
+        workflow
+    }
+}
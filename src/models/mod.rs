@@ -1,9 +1,30 @@
+pub mod action;
+pub mod compensation;
+pub mod correlation;
 pub mod edge;
 pub mod event;
+pub mod expression;
 pub mod gate;
+mod legacy;
 pub mod node;
+pub mod outbox;
+pub mod retry;
+pub mod sub_workflow;
+pub mod timer;
+pub mod transition;
+pub mod validation;
+pub mod variables;
 pub mod workflow;
 
+pub use action::{ActionRequest, NodeAction};
+pub use correlation::CorrelationKey;
 pub use event::Event;
-pub use node::{Node, NodeStatus};
+pub use node::{Node, NodeContext, NodeStatus};
+pub use outbox::OutboxMessage;
+pub use retry::RetryPolicy;
+pub use sub_workflow::SubWorkflow;
+pub use timer::{ScheduledTimer, TimerCommand, TimerRequest};
+pub use transition::Transition;
+pub use validation::ValidationError;
+pub use variables::Variables;
 pub use workflow::Workflow;
@@ -1,11 +1,97 @@
-use super::edge::Edge;
+use super::{
+    action::NodeAction,
+    compensation::Compensation,
+    edge::{Edge, LoopBack},
+    outbox::OutboxMessage,
+    retry::RetryPolicy,
+    sub_workflow::SubWorkflow,
+    timer::TimerRequest,
+    variables::Variables,
+};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use thiserror::Error;
+use time::OffsetDateTime;
+
+/// Error returned by a node behavior. The message is kept as the reason the
+/// node failed.
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{0}")]
+pub struct BehaviorError(pub String);
+
+impl BehaviorError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self(reason.into())
+    }
+}
+
+/// Handed to behavior hooks. Side effects are emitted as outbox messages
+/// rather than performed directly, so they only go out once the workflow
+/// change that caused them has been saved. Variable writes and emitted
+/// messages only take effect if the hook succeeds.
+#[derive(Debug)]
+pub struct NodeContext {
+    node_id: NodeId,
+    variables: Variables,
+    outbox: Vec<OutboxMessage>,
+}
+
+impl NodeContext {
+    pub fn new(node_id: NodeId, variables: Variables) -> Self {
+        Self {
+            node_id,
+            variables,
+            outbox: Vec::new(),
+        }
+    }
+
+    pub fn node_id(&self) -> &NodeId {
+        &self.node_id
+    }
+
+    pub fn emit(&mut self, topic: impl Into<String>, payload: serde_json::Value) {
+        self.outbox.push(OutboxMessage {
+            node_id: self.node_id.clone(),
+            topic: topic.into(),
+            payload,
+        });
+    }
+
+    pub fn variables(&self) -> &Variables {
+        &self.variables
+    }
+
+    pub fn variables_mut(&mut self) -> &mut Variables {
+        &mut self.variables
+    }
+
+    /// The workflow variables as the hook left them, and the messages it
+    /// emitted.
+    pub fn into_parts(self) -> (Variables, Vec<OutboxMessage>) {
+        (self.variables, self.outbox)
+    }
+}
 
 #[typetag::serde(tag = "type")]
 pub trait NodeBehavior: Send + Sync + Debug {
-    fn on_activated(&self) -> Option<String>;
-    fn on_completed(&self);
+    fn on_activated(
+        &self,
+        context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError>;
+    fn on_completed(&self, context: &mut NodeContext) -> Result<(), BehaviorError>;
+}
+
+/// What happens to the rest of the workflow when a node's behavior fails.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub enum FailurePolicy {
+    /// The whole workflow fails and stops processing.
+    #[default]
+    FailWorkflow,
+    /// Only the node fails; other branches carry on, and its successors are
+    /// never started.
+    Continue,
+    /// The node fails and the given node is activated in its place.
+    ErrorEdge(NodeId),
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -15,6 +101,31 @@ pub struct Node {
     pub status: NodeStatus,
     pub edges: Vec<Edge>,
     pub behavior: Box<dyn NodeBehavior>,
+    #[serde(default)]
+    pub on_failure: FailurePolicy,
+    /// Retries for failing behavior hooks. `None` fails the node on the
+    /// first error.
+    #[serde(default)]
+    pub retry: Option<RetryPolicy>,
+    /// Asynchronous side effect run in the background on activation or
+    /// completion.
+    #[serde(default)]
+    pub action: Option<Box<dyn NodeAction>>,
+    /// Undoes the node's work if the workflow fails or is cancelled after
+    /// the node completed.
+    #[serde(default)]
+    pub compensation: Option<Box<dyn Compensation>>,
+    /// Child workflow started when the node is activated, which the node
+    /// waits for.
+    #[serde(default)]
+    pub sub_workflow: Option<SubWorkflow>,
+    #[serde(default)]
+    pub loop_back: Option<LoopBack>,
+    /// When the node last became active.
+    #[serde(default)]
+    pub activated_at: Option<OffsetDateTime>,
+    #[serde(default)]
+    pub completed_at: Option<OffsetDateTime>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
@@ -22,7 +133,72 @@ pub enum NodeStatus {
     NotStarted,
     Active,
     Completed,
+    Failed,
+    /// Completed, then undone by its compensation.
+    Compensated,
+    /// Was active or waiting when its workflow was cancelled.
+    Cancelled,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-pub struct NodeId(pub usize);
+impl std::fmt::Display for NodeStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeStatus::NotStarted => write!(f, "not_started"),
+            NodeStatus::Active => write!(f, "active"),
+            NodeStatus::Completed => write!(f, "completed"),
+            NodeStatus::Failed => write!(f, "failed"),
+            NodeStatus::Compensated => write!(f, "compensated"),
+            NodeStatus::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+impl std::str::FromStr for NodeStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "not_started" => Ok(NodeStatus::NotStarted),
+            "active" => Ok(NodeStatus::Active),
+            "completed" => Ok(NodeStatus::Completed),
+            "failed" => Ok(NodeStatus::Failed),
+            "compensated" => Ok(NodeStatus::Compensated),
+            "cancelled" => Ok(NodeStatus::Cancelled),
+            _ => Err(format!("Invalid node status: {}", s)),
+        }
+    }
+}
+
+/// Stable key of a node within its workflow. Ids are names rather than
+/// positions so nodes can be added, reordered or removed between versions of
+/// a definition without breaking edges, joins or stored instances.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct NodeId(pub String);
+
+impl NodeId {
+    pub fn new(id: impl Into<String>) -> Self {
+        Self(id.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for NodeId {
+    fn from(id: &str) -> Self {
+        Self(id.to_string())
+    }
+}
+
+impl From<String> for NodeId {
+    fn from(id: String) -> Self {
+        Self(id)
+    }
+}
+
+impl std::fmt::Display for NodeId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,11 @@
+use super::node::NodeId;
+use serde::{Deserialize, Serialize};
+
+/// Message a behavior wants delivered once the workflow change that produced
+/// it has been committed.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OutboxMessage {
+    pub node_id: NodeId,
+    pub topic: String,
+    pub payload: serde_json::Value,
+}
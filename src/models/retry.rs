@@ -0,0 +1,128 @@
+use super::{node::NodeId, timer::human_duration};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How often a failing node behavior is retried before its node fails.
+/// Retries wait an exponentially growing delay, spread by a jitter that
+/// differs between workflows and nodes so nodes that failed together do not
+/// retry in lockstep.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// Total attempts including the first one.
+    pub max_attempts: u32,
+    #[serde(with = "human_duration")]
+    pub initial_backoff: Duration,
+    #[serde(default = "default_max_backoff", with = "human_duration")]
+    pub max_backoff: Duration,
+    #[serde(default = "default_multiplier")]
+    pub multiplier: f64,
+    /// Fraction of the delay that may be added or removed, between 0 and 1.
+    #[serde(default = "default_jitter")]
+    pub jitter: f64,
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(3600)
+}
+
+fn default_multiplier() -> f64 {
+    2.0
+}
+
+fn default_jitter() -> f64 {
+    0.2
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32, initial_backoff: Duration) -> Self {
+        Self {
+            max_attempts,
+            initial_backoff,
+            max_backoff: default_max_backoff(),
+            multiplier: default_multiplier(),
+            jitter: default_jitter(),
+        }
+    }
+
+    pub fn with_max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    pub fn with_multiplier(mut self, multiplier: f64) -> Self {
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Whether another attempt may follow the given number of failed ones.
+    pub fn allows_retry(&self, attempts: u32) -> bool {
+        attempts < self.max_attempts
+    }
+
+    /// Delay before the retry that follows `attempts` failed attempts, never
+    /// more than `max_backoff`. The jitter is derived from `seed` and the
+    /// attempt count rather than drawn at random, so replaying the same
+    /// events schedules the same retries.
+    pub fn backoff(&self, attempts: u32, seed: &str) -> Duration {
+        let exponent = attempts.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let base = (self.initial_backoff.as_secs_f64() * self.multiplier.powi(exponent))
+            .min(self.max_backoff.as_secs_f64());
+
+        let jitter = self.jitter.clamp(0.0, 1.0);
+        let factor = if jitter > 0.0 {
+            1.0 + jitter * (2.0 * unit_fraction(seed, attempts) - 1.0)
+        } else {
+            1.0
+        };
+
+        // Huge or non-finite delays from extreme settings fall back to the
+        // cap rather than panicking.
+        Duration::try_from_secs_f64((base * factor).max(0.0))
+            .map_or(self.max_backoff, |delay| delay.min(self.max_backoff))
+    }
+}
+
+/// Fraction in `[0, 1)`, spread evenly over seeds and the same for the same
+/// seed on every build and platform.
+fn unit_fraction(seed: &str, attempts: u32) -> f64 {
+    // FNV-1a, then the splitmix64 finalizer to spread similar seeds apart
+    let mut hash = 0xcbf2_9ce4_8422_2325_u64;
+    for byte in seed.bytes().chain(attempts.to_le_bytes()) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash = (hash ^ (hash >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    hash = (hash ^ (hash >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    hash ^= hash >> 31;
+    (hash >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+/// Which hook of a node is being retried.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RetryHook {
+    Activated,
+    /// The completion hook, with the targets whose edges had passed so they
+    /// can be activated once it succeeds.
+    Completed {
+        targets: Vec<NodeId>,
+    },
+    /// The completion hook of a node taking its loop-back edge.
+    Looped,
+}
+
+/// Retry progress of one node, persisted with the workflow so attempt
+/// counts survive restarts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RetryState {
+    pub hook: RetryHook,
+    /// Failed attempts so far.
+    pub attempts: u32,
+    pub last_error: String,
+    /// Timer that triggers the next attempt.
+    pub timer_id: String,
+}
@@ -0,0 +1,60 @@
+use super::{
+    gate::{Condition, EvaluationContext},
+    node::NodeId,
+    workflow::WorkflowStatus,
+    Event,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Makes a node start a child workflow for the same user when it is
+/// activated. The node waits until the child finishes: it fails if the
+/// child fails or is cancelled, and otherwise its edges are evaluated from
+/// the `Event::ChildFinished` on, like those of any other active node.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubWorkflow {
+    /// Name of the stored definition the child is started from, at its
+    /// latest version.
+    pub definition: String,
+}
+
+impl SubWorkflow {
+    pub fn new(definition: impl Into<String>) -> Self {
+        Self {
+            definition: definition.into(),
+        }
+    }
+}
+
+/// Child workflow the engine wants started, drained by the caller and
+/// created when the parent is saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChildRequest {
+    pub child_id: Uuid,
+    pub node_id: NodeId,
+    pub definition: String,
+}
+
+/// The parent workflow and node that started a child workflow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParentLink {
+    pub workflow_id: Uuid,
+    pub node_id: NodeId,
+}
+
+/// Passes once the child workflow of `node_id` has completed.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubWorkflowCompleted {
+    pub node_id: NodeId,
+}
+
+#[typetag::serde]
+impl Condition for SubWorkflowCompleted {
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        matches!(
+            context.event,
+            Event::ChildFinished { node_id, status: WorkflowStatus::Completed, .. }
+                if *node_id == self.node_id
+        )
+    }
+}
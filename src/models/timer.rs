@@ -0,0 +1,103 @@
+use super::node::NodeId;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::OffsetDateTime;
+
+/// Timer requested by a node behavior when the node is activated.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimerRequest {
+    pub timer_id: String,
+    pub delay: Duration,
+}
+
+impl TimerRequest {
+    pub fn new(timer_id: impl Into<String>, delay: Duration) -> Self {
+        Self {
+            timer_id: timer_id.into(),
+            delay,
+        }
+    }
+}
+
+/// Timer that has been handed to the timer service and has not fired yet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ScheduledTimer {
+    pub timer_id: String,
+    pub node_id: NodeId,
+    pub fire_at: OffsetDateTime,
+}
+
+/// Change to the persisted timers produced while processing an event.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimerCommand {
+    Schedule(ScheduledTimer),
+    Cancel { node_id: NodeId },
+}
+
+/// Serde helper for durations in definitions, which can be written as
+/// `"5s"`, `"250ms"`, `"3d"` (units `ns`, `us`, `ms`, `s`, `m`, `h` and
+/// `d`) or a number of milliseconds. Serde's own `{"secs": 5, "nanos": 0}`
+/// form is still accepted. Binary formats such as the legacy bincode
+/// workflow blob keep serde's encoding.
+pub(crate) mod human_duration {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::time::Duration;
+
+    const UNITS: [(&str, u128); 7] = [
+        ("d", 86_400_000_000_000),
+        ("h", 3_600_000_000_000),
+        ("m", 60_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+    ];
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Millis(u64),
+        Text(String),
+        Serde(Duration),
+    }
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return duration.serialize(serializer);
+        }
+        let nanos = duration.as_nanos();
+        let (unit, size) = UNITS
+            .iter()
+            .find(|(_, size)| nanos > 0 && nanos.is_multiple_of(*size))
+            .copied()
+            .unwrap_or(("s", 1_000_000_000));
+        serializer.serialize_str(&format!("{}{}", nanos / size, unit))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        if !deserializer.is_human_readable() {
+            return Duration::deserialize(deserializer);
+        }
+        match Repr::deserialize(deserializer)? {
+            Repr::Millis(millis) => Ok(Duration::from_millis(millis)),
+            Repr::Text(text) => parse(&text).ok_or_else(|| {
+                serde::de::Error::custom(format!(
+                    "invalid duration `{}`, expected e.g. \"5s\", \"250ms\" or a number of milliseconds",
+                    text
+                ))
+            }),
+            Repr::Serde(duration) => Ok(duration),
+        }
+    }
+
+    fn parse(text: &str) -> Option<Duration> {
+        let text = text.trim();
+        let split = text.find(|c: char| !c.is_ascii_digit())?;
+        let (amount, unit) = text.split_at(split);
+        let amount: u128 = amount.parse().ok()?;
+        let size = UNITS.iter().find(|(name, _)| *name == unit.trim())?.1;
+        let nanos = amount.checked_mul(size)?;
+        let secs = u64::try_from(nanos / 1_000_000_000).ok()?;
+        Some(Duration::new(secs, (nanos % 1_000_000_000) as u32))
+    }
+}
@@ -0,0 +1,18 @@
+use super::{node::NodeId, Event, NodeStatus};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+/// Record of a single node status change and what caused it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transition {
+    pub node_id: NodeId,
+    pub from: NodeStatus,
+    pub to: NodeStatus,
+    pub event: Event,
+    /// Gate of the edge that activated the node, serialized in the
+    /// definition format. `None` for transitions not caused by an edge.
+    pub gate: Option<serde_json::Value>,
+    /// Reason a node moved to `Failed` or `Cancelled`.
+    pub error: Option<String>,
+    pub at: OffsetDateTime,
+}
@@ -0,0 +1,234 @@
+use super::{
+    node::{FailurePolicy, NodeId},
+    workflow::WorkflowStatus,
+    NodeStatus, Workflow,
+};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+pub enum ValidationError {
+    #[error("Node id {0} is used by more than one node")]
+    DuplicateNodeId(NodeId),
+    #[error("Edge from node {node} points at missing node {target}")]
+    DanglingEdge { node: NodeId, target: NodeId },
+    #[error("Error edge from node {node} points at missing node {target}")]
+    DanglingErrorEdge { node: NodeId, target: NodeId },
+    #[error("Join on the edge from node {node} waits on missing node {waits_on}")]
+    UnknownJoinNode { node: NodeId, waits_on: NodeId },
+    #[error("Join on the edge from node {node} to {target} waits on node {waits_on}, which cannot complete before it")]
+    JoinCannotPrecede {
+        node: NodeId,
+        target: NodeId,
+        waits_on: NodeId,
+    },
+    #[error("Join on the edge from node {node} needs {quorum} of its {nodes} nodes to arrive")]
+    InvalidJoinQuorum {
+        node: NodeId,
+        quorum: usize,
+        nodes: usize,
+    },
+    #[error("Loop-back edge from node {node} points at missing node {target}")]
+    DanglingLoopBack { node: NodeId, target: NodeId },
+    #[error("Loop-back edge from node {node} leads to {target}, which does not lead back to it")]
+    LoopBackNotUpstream { node: NodeId, target: NodeId },
+    #[error("Workflow has no active node to start from")]
+    NoInitialNode,
+    #[error("Node {0} cannot be reached from any started node")]
+    UnreachableNode(NodeId),
+    #[error("Edge from node {node} to {target} closes a cycle")]
+    Cycle { node: NodeId, target: NodeId },
+}
+
+impl Workflow {
+    /// Checks the graph for structural problems that would otherwise only
+    /// show up (or panic) while events are being processed.
+    pub fn validate(&self) -> Result<(), Vec<ValidationError>> {
+        let mut errors = Vec::new();
+
+        let mut index = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Entry::Vacant(entry) = index.entry(node.id.clone()) {
+                entry.insert(i);
+            } else {
+                errors.push(ValidationError::DuplicateNodeId(node.id.clone()));
+            }
+        }
+
+        // Edges and joins must only reference existing nodes
+        let mut adjacency = vec![Vec::new(); self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            for edge in &node.edges {
+                match index.get(&edge.target) {
+                    Some(&target_idx) => adjacency[i].push(target_idx),
+                    None => errors.push(ValidationError::DanglingEdge {
+                        node: node.id.clone(),
+                        target: edge.target.clone(),
+                    }),
+                }
+                for waits_on in edge.gate.waited_nodes() {
+                    if !index.contains_key(&waits_on) {
+                        errors.push(ValidationError::UnknownJoinNode {
+                            node: node.id.clone(),
+                            waits_on,
+                        });
+                    }
+                }
+                for join in edge.gate.joins() {
+                    if join.required() == 0 || join.required() > join.nodes.len() {
+                        errors.push(ValidationError::InvalidJoinQuorum {
+                            node: node.id.clone(),
+                            quorum: join.required(),
+                            nodes: join.nodes.len(),
+                        });
+                    }
+                }
+            }
+            if let FailurePolicy::ErrorEdge(target) = &node.on_failure {
+                match index.get(target) {
+                    Some(&target_idx) => adjacency[i].push(target_idx),
+                    None => errors.push(ValidationError::DanglingErrorEdge {
+                        node: node.id.clone(),
+                        target: target.clone(),
+                    }),
+                }
+            }
+            // Only the exit of a loop-back edge leads forward
+            if let Some(loop_back) = &node.loop_back {
+                for target in [&loop_back.target, &loop_back.exit] {
+                    if !index.contains_key(target) {
+                        errors.push(ValidationError::DanglingLoopBack {
+                            node: node.id.clone(),
+                            target: target.clone(),
+                        });
+                    }
+                }
+                if let Some(&exit_idx) = index.get(&loop_back.exit) {
+                    adjacency[i].push(exit_idx);
+                }
+            }
+        }
+
+        // A loop-back edge must lead to a node its own node can be reached
+        // from
+        for (i, node) in self.nodes.iter().enumerate() {
+            let Some(loop_back) = &node.loop_back else {
+                continue;
+            };
+            if let Some(&target_idx) = index.get(&loop_back.target) {
+                if !reachable_from(&adjacency, &[target_idx]).contains(&i) {
+                    errors.push(ValidationError::LoopBackNotUpstream {
+                        node: node.id.clone(),
+                        target: loop_back.target.clone(),
+                    });
+                }
+            }
+        }
+
+        // Every node must be reachable from a node that has already started
+        let roots: Vec<usize> = self
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, node)| node.status != NodeStatus::NotStarted)
+            .map(|(i, _)| i)
+            .collect();
+        if self.status == WorkflowStatus::Active
+            && !self.nodes.iter().any(|n| n.status == NodeStatus::Active)
+        {
+            errors.push(ValidationError::NoInitialNode);
+        }
+        let reachable = reachable_from(&adjacency, &roots);
+        for (i, node) in self.nodes.iter().enumerate() {
+            if !reachable.contains(&i) {
+                errors.push(ValidationError::UnreachableNode(node.id.clone()));
+            }
+        }
+
+        for (node, target) in back_edges(&adjacency) {
+            errors.push(ValidationError::Cycle {
+                node: self.nodes[node].id.clone(),
+                target: self.nodes[target].id.clone(),
+            });
+        }
+
+        // A join can only wait on nodes other than its own source that are not
+        // downstream of its target
+        for (i, node) in self.nodes.iter().enumerate() {
+            for edge in &node.edges {
+                let Some(&target_idx) = index.get(&edge.target) else {
+                    continue;
+                };
+                let downstream = reachable_from(&adjacency, &[target_idx]);
+                for waits_on in edge.gate.waited_nodes() {
+                    if let Some(waits_on_idx) = index.get(&waits_on) {
+                        if *waits_on_idx == i || downstream.contains(waits_on_idx) {
+                            errors.push(ValidationError::JoinCannotPrecede {
+                                node: self.nodes[i].id.clone(),
+                                target: edge.target.clone(),
+                                waits_on,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
+
+fn reachable_from(adjacency: &[Vec<usize>], roots: &[usize]) -> HashSet<usize> {
+    let mut seen: HashSet<usize> = roots.iter().copied().collect();
+    let mut stack = roots.to_vec();
+    while let Some(node) = stack.pop() {
+        for &target in &adjacency[node] {
+            if seen.insert(target) {
+                stack.push(target);
+            }
+        }
+    }
+    seen
+}
+
+/// Edges that point back at a node still on the depth-first search path.
+fn back_edges(adjacency: &[Vec<usize>]) -> Vec<(usize, usize)> {
+    #[derive(Clone, Copy, PartialEq)]
+    enum Mark {
+        Unvisited,
+        OnPath,
+        Done,
+    }
+
+    let mut marks = vec![Mark::Unvisited; adjacency.len()];
+    let mut found = Vec::new();
+    for start in 0..adjacency.len() {
+        if marks[start] != Mark::Unvisited {
+            continue;
+        }
+        marks[start] = Mark::OnPath;
+        let mut stack = vec![(start, 0)];
+        while let Some((node, next)) = stack.last_mut() {
+            let node = *node;
+            if let Some(&target) = adjacency[node].get(*next) {
+                *next += 1;
+                match marks[target] {
+                    Mark::Unvisited => {
+                        marks[target] = Mark::OnPath;
+                        stack.push((target, 0));
+                    }
+                    Mark::OnPath => found.push((node, target)),
+                    Mark::Done => {}
+                }
+            } else {
+                marks[node] = Mark::Done;
+                stack.pop();
+            }
+        }
+    }
+    found
+}
@@ -0,0 +1,128 @@
+use super::gate::{Condition, EvaluationContext};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum VariableError {
+    #[error("variable {key} could not be encoded: {source}")]
+    Encode {
+        key: String,
+        source: serde_json::Error,
+    },
+    #[error("variable {key} does not hold the requested type: {source}")]
+    Decode {
+        key: String,
+        source: serde_json::Error,
+    },
+}
+
+/// Workflow-scoped key/value store, such as a cart value or the id of the
+/// last purchase. Gates read it through their `EvaluationContext` and
+/// behaviors write it through their `NodeContext`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Variables(#[serde(with = "json_in_binary")] BTreeMap<String, Value>);
+
+impl Variables {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads a variable as `T`, or `None` if it is not set.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> Result<Option<T>, VariableError> {
+        self.0
+            .get(key)
+            .map(|value| {
+                T::deserialize(value).map_err(|source| VariableError::Decode {
+                    key: key.to_string(),
+                    source,
+                })
+            })
+            .transpose()
+    }
+
+    pub fn get_value(&self, key: &str) -> Option<&Value> {
+        self.0.get(key)
+    }
+
+    pub fn set<T: Serialize>(
+        &mut self,
+        key: impl Into<String>,
+        value: T,
+    ) -> Result<(), VariableError> {
+        let key = key.into();
+        match serde_json::to_value(value) {
+            Ok(value) => {
+                self.0.insert(key, value);
+                Ok(())
+            }
+            Err(source) => Err(VariableError::Encode { key, source }),
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.0.remove(key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.0.contains_key(key)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// Serde helper for JSON values kept in the workflow. Binary formats such
+/// as the legacy bincode workflow blob cannot decode a `serde_json::Value`,
+/// so there the value travels as a JSON string instead.
+pub(crate) mod json_in_binary {
+    use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<T: Serialize, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            value.serialize(serializer)
+        } else {
+            let json = serde_json::to_string(value).map_err(serde::ser::Error::custom)?;
+            serializer.serialize_str(&json)
+        }
+    }
+
+    pub fn deserialize<'de, T: DeserializeOwned, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<T, D::Error> {
+        if deserializer.is_human_readable() {
+            T::deserialize(deserializer)
+        } else {
+            let json = String::deserialize(deserializer)?;
+            serde_json::from_str(&json).map_err(serde::de::Error::custom)
+        }
+    }
+}
+
+/// Passes while the variable `key` holds exactly `value`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VariableEquals {
+    pub key: String,
+    #[serde(with = "json_in_binary")]
+    pub value: Value,
+}
+
+#[typetag::serde]
+impl Condition for VariableEquals {
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        context.variables.get_value(&self.key) == Some(&self.value)
+    }
+}
@@ -1,21 +1,39 @@
-use super::{Event, Node, NodeStatus};
+use super::{
+    action::{ActionHook, ActionRequest},
+    compensation::CompensationState,
+    correlation::CorrelationKey,
+    gate::{deadline_timer_id, EvaluationContext},
+    legacy::LegacyWorkflow,
+    node::{BehaviorError, FailurePolicy, NodeContext, NodeId},
+    outbox::OutboxMessage,
+    retry::{RetryHook, RetryState},
+    sub_workflow::{ChildRequest, ParentLink},
+    variables::Variables,
+    Event, Node, NodeStatus, ScheduledTimer, TimerCommand, TimerRequest, Transition,
+    ValidationError,
+};
 use bincode::{DefaultOptions, Options};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use thiserror::Error;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum WorkflowStatus {
     Active,
     Completed,
     Failed,
+    Cancelled,
 }
 
-impl ToString for WorkflowStatus {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for WorkflowStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            WorkflowStatus::Active => "active".to_string(),
-            WorkflowStatus::Completed => "completed".to_string(),
-            WorkflowStatus::Failed => "failed".to_string(),
+            WorkflowStatus::Active => write!(f, "active"),
+            WorkflowStatus::Completed => write!(f, "completed"),
+            WorkflowStatus::Failed => write!(f, "failed"),
+            WorkflowStatus::Cancelled => write!(f, "cancelled"),
         }
     }
 }
@@ -28,34 +46,391 @@ impl std::str::FromStr for WorkflowStatus {
             "active" => Ok(WorkflowStatus::Active),
             "completed" => Ok(WorkflowStatus::Completed),
             "failed" => Ok(WorkflowStatus::Failed),
+            "cancelled" => Ok(WorkflowStatus::Cancelled),
             _ => Err(format!("Invalid workflow status: {}", s)),
         }
     }
 }
 
+/// Upper bound on node evaluations for a single event, guarding against
+/// runaway activation.
+pub const DEFAULT_MAX_STEPS: usize = 1_000_000;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum WorkflowError {
+    #[error("Event processing stopped after {0} steps")]
+    StepLimitExceeded(usize),
+}
+
+/// Marks a versioned workflow blob. Blobs without it were written by the
+/// original bincode encoding, which starts with the length prefix of the
+/// workflow id and so never begins with these bytes.
+pub const BLOB_MAGIC: &[u8; 4] = b"ARWF";
+
+/// Version of the blob body written by `Workflow::to_bytes`. Version 1 is
+/// JSON, which unlike bincode honours `#[serde(default)]`, so fields added
+/// later still decode from older rows.
+pub const BLOB_VERSION: u8 = 1;
+
+#[derive(Error, Debug)]
+pub enum BlobError {
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("Unsupported workflow blob version {0}")]
+    UnsupportedVersion(u8),
+}
+
+fn default_max_steps() -> usize {
+    DEFAULT_MAX_STEPS
+}
+
+/// Identifies the stored definition version a workflow was started from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DefinitionRef {
+    pub name: String,
+    pub version: i32,
+}
+
+impl std::fmt::Display for DefinitionRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} v{}", self.name, self.version)
+    }
+}
+
+/// The node failure that failed a workflow.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeFailure {
+    pub node_id: NodeId,
+    pub reason: String,
+}
+
+impl std::fmt::Display for NodeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "node {} failed: {}", self.node_id, self.reason)
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Workflow {
     pub id: Uuid,
     pub user_id: Uuid,
     pub name: String,
+    /// `None` for workflows built in code rather than from a stored
+    /// definition.
+    #[serde(default)]
+    pub definition: Option<DefinitionRef>,
+    /// Restricts the events dispatched with a key to the instances started
+    /// with it, see `Dispatcher::dispatch_correlated`.
+    #[serde(default)]
+    pub correlation_key: Option<CorrelationKey>,
     pub nodes: Vec<Node>,
     pub status: WorkflowStatus,
+    /// Set when a node failure failed the workflow. A workflow that carried
+    /// on past failures under `FailurePolicy::Continue` keeps the first of
+    /// them here, even once it completes.
+    #[serde(default)]
+    pub failure: Option<NodeFailure>,
+    /// Set when the workflow was cancelled.
+    #[serde(default)]
+    pub cancel_reason: Option<String>,
+    #[serde(default)]
+    pub scheduled_timers: Vec<ScheduledTimer>,
+    /// Values shared by the whole workflow, written by behaviors and read by
+    /// gates.
+    #[serde(default)]
+    pub variables: Variables,
+    /// Nodes waiting to retry a failed behavior hook.
+    #[serde(default)]
+    pub retries: HashMap<NodeId, RetryState>,
+    /// Number of actions requested so far, used to give each one an id that
+    /// is the same when events are replayed.
+    #[serde(default)]
+    pub action_seq: u64,
+    /// Id of the last action each node requested, the only one whose result
+    /// it still takes.
+    #[serde(default)]
+    pub pending_actions: HashMap<NodeId, String>,
+    /// Nodes with a compensation whose completion hook has run, in that
+    /// order.
+    #[serde(default)]
+    pub completion_order: Vec<NodeId>,
+    /// Set once the workflow has failed or been cancelled and has completed
+    /// nodes to compensate.
+    #[serde(default)]
+    pub compensation: Option<CompensationState>,
+    /// Set for workflows started by a sub-workflow node.
+    #[serde(default)]
+    pub parent: Option<ParentLink>,
+    /// Sub-workflow nodes waiting for their child, and the child each one
+    /// waits for.
+    #[serde(default)]
+    pub children: HashMap<NodeId, Uuid>,
+    /// How many times loop-back edges have started each node over.
+    #[serde(default)]
+    pub iterations: HashMap<NodeId, u32>,
+    #[serde(default = "default_max_steps")]
+    pub max_steps: usize,
+    /// Version of the stored row this workflow was loaded from, or 0 if it
+    /// has never been saved. Kept in its own column rather than the blob.
+    #[serde(skip)]
+    pub version: i64,
+    #[serde(skip)]
+    timer_commands: Vec<TimerCommand>,
+    #[serde(skip)]
+    action_requests: Vec<ActionRequest>,
+    #[serde(skip)]
+    outbox: Vec<OutboxMessage>,
+    #[serde(skip)]
+    outbox_cancelled: bool,
+    #[serde(skip)]
+    journal: Vec<Transition>,
+    #[serde(skip)]
+    child_requests: Vec<ChildRequest>,
+    #[serde(skip)]
+    child_cancellations: Vec<Uuid>,
+    #[serde(skip)]
+    parent_notification: Option<Event>,
+    /// Time the current event is processed at.
+    #[serde(skip)]
+    clock: Option<OffsetDateTime>,
+    #[serde(skip)]
+    index: HashMap<NodeId, usize>,
+    #[serde(skip)]
+    join_waiters: HashMap<NodeId, Vec<usize>>,
+    /// Nodes that took their loop-back edge on the current event.
+    #[serde(skip)]
+    looped: HashSet<usize>,
 }
 
 impl Workflow {
     pub fn new(nodes: Vec<Node>) -> Self {
-        Self {
+        Self::new_at(nodes, OffsetDateTime::now_utc())
+    }
+
+    /// Like `new`, but for a workflow created at `created_at`, which time
+    /// gates on the initial nodes measure from. Replays pass the time the
+    /// original instance was created.
+    pub fn new_at(nodes: Vec<Node>, created_at: OffsetDateTime) -> Self {
+        let mut workflow = Self {
             id: Uuid::new_v4(),
             user_id: Uuid::new_v4(),
             name: String::new(),
+            definition: None,
+            correlation_key: None,
             nodes,
             status: WorkflowStatus::Active,
+            failure: None,
+            cancel_reason: None,
+            scheduled_timers: Vec::new(),
+            variables: Variables::new(),
+            retries: HashMap::new(),
+            action_seq: 0,
+            pending_actions: HashMap::new(),
+            completion_order: Vec::new(),
+            compensation: None,
+            parent: None,
+            children: HashMap::new(),
+            iterations: HashMap::new(),
+            max_steps: DEFAULT_MAX_STEPS,
+            version: 0,
+            timer_commands: Vec::new(),
+            action_requests: Vec::new(),
+            outbox: Vec::new(),
+            outbox_cancelled: false,
+            journal: Vec::new(),
+            child_requests: Vec::new(),
+            child_cancellations: Vec::new(),
+            parent_notification: None,
+            clock: None,
+            index: HashMap::new(),
+            join_waiters: HashMap::new(),
+            looped: HashSet::new(),
+        };
+        workflow.reindex();
+        workflow.start_initial_nodes(created_at);
+        workflow
+    }
+
+    pub fn with_correlation_key(mut self, key: impl Into<CorrelationKey>) -> Self {
+        self.correlation_key = Some(key.into());
+        self
+    }
+
+    /// Stamps the nodes that start out active as activated at `now`,
+    /// schedules the deadlines on their edges, so time gates measure from
+    /// when the workflow was created, and requests their sub-workflows.
+    fn start_initial_nodes(&mut self, now: OffsetDateTime) {
+        self.clock = Some(now);
+        for node_idx in 0..self.nodes.len() {
+            let node = &mut self.nodes[node_idx];
+            if node.status == NodeStatus::Active && node.activated_at.is_none() {
+                node.activated_at = Some(now);
+                self.schedule_deadlines(node_idx);
+                self.request_child(node_idx);
+            }
+        }
+        self.clock = None;
+    }
+
+    /// Builds a workflow and rejects it if its graph does not validate.
+    pub fn try_new(nodes: Vec<Node>) -> Result<Self, Vec<ValidationError>> {
+        let workflow = Self::new(nodes);
+        workflow.validate()?;
+        Ok(workflow)
+    }
+
+    /// Rebuilds the id to position map. Lookups fall back to a scan when the
+    /// map is stale, so this only needs calling after editing `nodes` directly
+    /// to keep lookups fast.
+    pub fn reindex(&mut self) {
+        self.index = self
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.clone(), i))
+            .collect();
+    }
+
+    pub fn node_index(&self, id: &NodeId) -> Option<usize> {
+        match self.index.get(id) {
+            Some(&i) if self.nodes.get(i).is_some_and(|node| node.id == *id) => Some(i),
+            _ => self.nodes.iter().position(|node| node.id == *id),
         }
     }
 
-    pub fn process_event(&mut self, event: &Event) {
+    pub fn node(&self, id: &NodeId) -> Option<&Node> {
+        self.node_index(id).map(|i| &self.nodes[i])
+    }
+
+    pub fn node_mut(&mut self, id: &NodeId) -> Option<&mut Node> {
+        self.node_index(id).map(|i| &mut self.nodes[i])
+    }
+
+    /// Drains the timer changes produced since the last call so the caller
+    /// can persist them once the workflow itself has been saved.
+    pub fn take_timer_commands(&mut self) -> Vec<TimerCommand> {
+        std::mem::take(&mut self.timer_commands)
+    }
+
+    /// Drains the actions requested since the last call so the caller can
+    /// persist them for the action runner.
+    pub fn take_action_requests(&mut self) -> Vec<ActionRequest> {
+        std::mem::take(&mut self.action_requests)
+    }
+
+    /// Drains the messages behaviors emitted since the last call so they can
+    /// be written to the outbox in the same transaction as the workflow.
+    pub fn take_outbox(&mut self) -> Vec<OutboxMessage> {
+        std::mem::take(&mut self.outbox)
+    }
+
+    /// Whether the workflow was cancelled since the last call, so outbox
+    /// messages it emitted earlier must no longer be delivered.
+    pub fn take_outbox_cancellation(&mut self) -> bool {
+        std::mem::take(&mut self.outbox_cancelled)
+    }
+
+    /// Drains the child workflows requested since the last call so the
+    /// caller can create them once the workflow itself has been saved.
+    pub fn take_child_requests(&mut self) -> Vec<ChildRequest> {
+        std::mem::take(&mut self.child_requests)
+    }
+
+    /// Drains the children still running when the workflow finished, which
+    /// the caller must cancel.
+    pub fn take_child_cancellations(&mut self) -> Vec<Uuid> {
+        std::mem::take(&mut self.child_cancellations)
+    }
+
+    /// Takes the `Event::ChildFinished` to deliver to the parent workflow
+    /// once this child has finished.
+    pub fn take_parent_notification(&mut self) -> Option<Event> {
+        self.parent_notification.take()
+    }
+
+    /// Whether compensations are still due, which keeps a failed or
+    /// cancelled workflow waiting for its retry timers.
+    pub fn is_compensating(&self) -> bool {
+        self.compensation
+            .as_ref()
+            .is_some_and(|state| !state.is_finished())
+    }
+
+    /// Queues a timer change to be persisted with the next save.
+    pub(crate) fn push_timer_command(&mut self, command: TimerCommand) {
+        self.timer_commands.push(command);
+    }
+
+    /// Node transitions recorded since the journal was last taken.
+    pub fn journal(&self) -> &[Transition] {
+        &self.journal
+    }
+
+    /// Drains the journal so its transitions can be persisted.
+    pub fn take_journal(&mut self) -> Vec<Transition> {
+        std::mem::take(&mut self.journal)
+    }
+
+    /// Applies an event to the workflow. Active nodes whose edges pass
+    /// activate their targets, which are evaluated against the same event in
+    /// turn until no further node can move. A failed or cancelled workflow
+    /// ignores further events apart from its compensation retry timers.
+    pub fn process_event(&mut self, event: &Event) -> Result<(), WorkflowError> {
+        self.process_event_at(event, OffsetDateTime::now_utc())
+    }
+
+    /// Applies an event as if it were processed at `now`, which time-based
+    /// gates, node timestamps and new timers are measured against. Replays
+    /// pass the time the event was recorded.
+    pub fn process_event_at(
+        &mut self,
+        event: &Event,
+        now: OffsetDateTime,
+    ) -> Result<(), WorkflowError> {
+        self.clock = Some(now);
+        let result = self.run_event(event);
+        self.clock = None;
+        result
+    }
+
+    fn run_event(&mut self, event: &Event) -> Result<(), WorkflowError> {
+        if matches!(
+            self.status,
+            WorkflowStatus::Failed | WorkflowStatus::Cancelled
+        ) {
+            self.resume_compensation(event);
+            return Ok(());
+        }
+
+        if let Event::Cancelled { reason } = event {
+            self.cancel_nodes(event, reason);
+            return Ok(());
+        }
+
+        // Results of actions requested by an earlier activation of the node,
+        // or delivered before, are ignored
+        if let Event::Action {
+            node_id, action_id, ..
+        } = event
+        {
+            if self.pending_actions.get(node_id) != Some(action_id) {
+                return Ok(());
+            }
+            self.pending_actions.remove(node_id);
+        }
+
+        // A delivered timer is no longer pending
+        if let Event::Timer { timer_id } = event {
+            self.scheduled_timers.retain(|t| t.timer_id != *timer_id);
+        }
+
+        self.index_join_waiters();
+        self.looped.clear();
+
         // Start with all active nodes
-        let active_nodes: Vec<_> = self
+        let mut worklist: VecDeque<usize> = self
             .nodes
             .iter()
             .enumerate()
@@ -63,40 +438,110 @@ impl Workflow {
             .map(|(i, _)| i)
             .collect();
 
-        // Process each active node recursively
-        for node_idx in active_nodes {
-            self.process_node(node_idx, event);
+        // A delivered retry timer runs the failed hook of its node again
+        if let Event::Timer { timer_id } = event {
+            let retrying = self
+                .retries
+                .iter()
+                .find(|(_, state)| state.timer_id == *timer_id)
+                .and_then(|(node_id, _)| self.node_index(node_id));
+            if let Some(node_idx) = retrying {
+                self.retry_hook(node_idx, event, &mut worklist);
+            }
+        }
+
+        // A finished child releases its node, or fails it. A child the node
+        // no longer waits for, such as one a loop-back cancelled before
+        // starting the node over, is ignored.
+        if let Event::ChildFinished {
+            node_id,
+            child_id,
+            status,
+            reason,
+        } = event
+        {
+            let waiting = if self.children.get(node_id) == Some(child_id) {
+                self.children.remove(node_id);
+                self.node_index(node_id)
+            } else {
+                None
+            };
+            if let Some(node_idx) = waiting {
+                if *status != WorkflowStatus::Completed {
+                    let mut error = format!("sub-workflow {} {}", child_id, status);
+                    if let Some(reason) = reason {
+                        error = format!("{}: {}", error, reason);
+                    }
+                    self.fail_node(node_idx, event, BehaviorError(error), &mut worklist);
+                }
+            }
         }
 
-        // Check if workflow is completed
-        if self
-            .nodes
-            .iter()
-            .all(|node| node.status == NodeStatus::Completed)
+        let mut steps = 0;
+        while let Some(node_idx) = worklist.pop_front() {
+            steps += 1;
+            if steps > self.max_steps {
+                return Err(WorkflowError::StepLimitExceeded(self.max_steps));
+            }
+            if self.nodes[node_idx].status == NodeStatus::Active && !self.is_waiting(node_idx) {
+                self.process_node(node_idx, event, &mut worklist);
+            }
+        }
+
+        // Only active nodes start others, so the workflow is completed once
+        // none is left. Nodes that failed without failing the workflow count
+        // as finished, and nodes on branches that were not taken stay
+        // `NotStarted`.
+        if self.status == WorkflowStatus::Active
+            && !self
+                .nodes
+                .iter()
+                .any(|node| node.status == NodeStatus::Active)
         {
             println!("Workflow completed!");
             self.status = WorkflowStatus::Completed;
+            self.finish();
         }
+
+        Ok(())
+    }
+
+    /// Nodes waiting to retry a hook or for their child workflow do not move
+    /// until it succeeds or finishes.
+    fn is_waiting(&self, node_idx: usize) -> bool {
+        let node_id = &self.nodes[node_idx].id;
+        (!self.retries.is_empty() && self.retries.contains_key(node_id))
+            || (!self.children.is_empty() && self.children.contains_key(node_id))
     }
 
-    fn process_node(&mut self, node_idx: usize, event: &Event) {
-        // Complete nodes with no edges
+    fn process_node(&mut self, node_idx: usize, event: &Event, worklist: &mut VecDeque<usize>) {
+        if self.take_loop_back(node_idx, event, worklist) {
+            return;
+        }
+
+        // Complete nodes with no edges, unless they wait to loop back
         if self.nodes[node_idx].edges.is_empty() {
-            self.nodes[node_idx].status = NodeStatus::Completed;
-            self.nodes[node_idx].behavior.on_completed();
+            if self.nodes[node_idx].loop_back.is_none() {
+                self.run_completion(node_idx, event, Vec::new(), worklist);
+            }
             return;
         }
 
         let mut all_edges_activated = true;
-        let mut any_edge_activated = false;
 
         // Collect target indices first to avoid borrow issues
         let mut targets = Vec::new();
-        for edge in &self.nodes[node_idx].edges {
-            if edge.gate.evaluate(&self.nodes, event) {
-                if let Some(target_idx) = self.nodes.iter().position(|n| n.id == edge.target) {
-                    if self.nodes[target_idx].status == NodeStatus::NotStarted {
-                        targets.push((target_idx, edge.target.0));
+        let node = &self.nodes[node_idx];
+        let context = EvaluationContext::new(event, &self.variables)
+            .with_node(node)
+            .at(self.now());
+        for edge in &node.edges {
+            if edge.gate.evaluate(self, &context) {
+                if let Some(target_idx) = self.node_index(&edge.target) {
+                    if self.nodes[target_idx].status == NodeStatus::NotStarted
+                        && !targets.iter().any(|(idx, _)| *idx == target_idx)
+                    {
+                        targets.push((target_idx, serde_json::to_value(&edge.gate).ok()));
                     }
                 }
             } else {
@@ -104,38 +549,696 @@ impl Workflow {
             }
         }
 
-        // Process collected targets
-        for (target_idx, target_id) in targets {
-            self.nodes[target_idx].status = NodeStatus::Active;
-            if let Some(timer_id) = self.nodes[target_idx].behavior.on_activated() {
-                println!("Timer node {} activated with ID {}", target_id, timer_id);
+        // Complete node if all edges activated and at least one was activated
+        if all_edges_activated && !targets.is_empty() {
+            self.run_completion(node_idx, event, targets, worklist);
+        } else {
+            self.activate_all(targets, event, worklist);
+        }
+    }
+
+    /// Takes the node's loop-back edge if its gate passes, and returns
+    /// whether it did. The edge leads back to its target until the target
+    /// has run `max_iterations` times, and to its exit after that. It is
+    /// taken at most once per event, so the nodes it starts over wait for
+    /// the next one rather than looping on the event that restarted them.
+    fn take_loop_back(
+        &mut self,
+        node_idx: usize,
+        event: &Event,
+        worklist: &mut VecDeque<usize>,
+    ) -> bool {
+        let node = &self.nodes[node_idx];
+        let Some(loop_back) = &node.loop_back else {
+            return false;
+        };
+        if self.looped.contains(&node_idx) {
+            return false;
+        }
+        let context = EvaluationContext::new(event, &self.variables)
+            .with_node(node)
+            .at(self.now());
+        if !loop_back.gate.evaluate(self, &context) {
+            return false;
+        }
+        let Some(target_idx) = self.node_index(&loop_back.target) else {
+            return false;
+        };
+
+        let gate = serde_json::to_value(&loop_back.gate).ok();
+        let runs = self.iterations.get(&loop_back.target).copied().unwrap_or(0) + 1;
+        if runs < loop_back.max_iterations {
+            self.run_loop_back(node_idx, target_idx, event, gate, worklist);
+        } else {
+            let targets = self
+                .node_index(&loop_back.exit)
+                .filter(|&idx| self.nodes[idx].status == NodeStatus::NotStarted)
+                .map(|idx| (idx, gate))
+                .into_iter()
+                .collect();
+            self.run_completion(node_idx, event, targets, worklist);
+        }
+        true
+    }
+
+    /// Completes a node taking its loop-back edge, resets the nodes from the
+    /// loop's target up to it and activates the target again.
+    fn run_loop_back(
+        &mut self,
+        node_idx: usize,
+        target_idx: usize,
+        event: &Event,
+        gate: Option<serde_json::Value>,
+        worklist: &mut VecDeque<usize>,
+    ) {
+        if !self.run_completion_hook(node_idx, event, RetryHook::Looped, worklist) {
+            return;
+        }
+        self.looped.insert(node_idx);
+        self.complete_node(node_idx, event);
+        self.wake_joins(node_idx, worklist);
+
+        for idx in self.loop_body(target_idx, node_idx) {
+            self.reset_node(idx, event);
+        }
+        *self
+            .iterations
+            .entry(self.nodes[target_idx].id.clone())
+            .or_insert(0) += 1;
+        self.activate(target_idx, event, gate, worklist);
+    }
+
+    /// Nodes on the way from `target_idx` to `node_idx` along regular and
+    /// error edges, both included.
+    fn loop_body(&self, target_idx: usize, node_idx: usize) -> Vec<usize> {
+        let successors = |idx: usize| {
+            let node = &self.nodes[idx];
+            let error_edge = match &node.on_failure {
+                FailurePolicy::ErrorEdge(target) => Some(target),
+                FailurePolicy::FailWorkflow | FailurePolicy::Continue => None,
+            };
+            node.edges
+                .iter()
+                .map(|edge| &edge.target)
+                .chain(error_edge)
+                .filter_map(|id| self.node_index(id))
+                .collect::<Vec<_>>()
+        };
+
+        // Nodes reachable from the target, then those of them that lead to
+        // the node
+        let mut downstream = vec![false; self.nodes.len()];
+        let mut predecessors = vec![Vec::new(); self.nodes.len()];
+        let mut stack = vec![target_idx];
+        downstream[target_idx] = true;
+        while let Some(idx) = stack.pop() {
+            for next in successors(idx) {
+                predecessors[next].push(idx);
+                if !downstream[next] {
+                    downstream[next] = true;
+                    stack.push(next);
+                }
             }
-            self.process_node(target_idx, event);
-            any_edge_activated = true;
         }
 
-        // Complete node if all edges activated and at least one was activated
-        if all_edges_activated && any_edge_activated {
-            self.nodes[node_idx].status = NodeStatus::Completed;
-            self.nodes[node_idx].behavior.on_completed();
+        let mut body = vec![node_idx];
+        let mut in_body = vec![false; self.nodes.len()];
+        in_body[node_idx] = true;
+        let mut stack = vec![node_idx];
+        while let Some(idx) = stack.pop() {
+            for &previous in &predecessors[idx] {
+                if !in_body[previous] {
+                    in_body[previous] = true;
+                    body.push(previous);
+                    stack.push(previous);
+                }
+            }
         }
+        if !in_body[target_idx] {
+            body.push(target_idx);
+        }
+        body
     }
 
-    pub fn to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
-        let config = DefaultOptions::new()
-            .with_fixint_encoding()
-            .allow_trailing_bytes()
-            .with_native_endian()
-            .with_no_limit();
-        config.serialize(self)
+    /// Returns a node that has run to `NotStarted` so it can run again,
+    /// dropping its timers, pending retry and running child.
+    fn reset_node(&mut self, node_idx: usize, event: &Event) {
+        if self.nodes[node_idx].status == NodeStatus::NotStarted {
+            return;
+        }
+        self.set_status(node_idx, NodeStatus::NotStarted, event, None, None);
+        self.cancel_timers(node_idx);
+        let node_id = &self.nodes[node_idx].id;
+        self.retries.remove(node_id);
+        self.pending_actions.remove(node_id);
+        if let Some(child_id) = self.children.remove(node_id) {
+            self.child_cancellations.push(child_id);
+        }
+    }
+
+    /// Activates collected targets and queues them for evaluation, stopping
+    /// early if one of them fails the workflow.
+    fn activate_all(
+        &mut self,
+        targets: Vec<(usize, Option<serde_json::Value>)>,
+        event: &Event,
+        worklist: &mut VecDeque<usize>,
+    ) -> bool {
+        for (target_idx, gate) in targets {
+            self.activate(target_idx, event, gate, worklist);
+            if self.status == WorkflowStatus::Failed {
+                return false;
+            }
+        }
+        true
+    }
+
+    fn activate(
+        &mut self,
+        node_idx: usize,
+        event: &Event,
+        gate: Option<serde_json::Value>,
+        worklist: &mut VecDeque<usize>,
+    ) {
+        self.set_status(node_idx, NodeStatus::Active, event, gate, None);
+        self.run_activation(node_idx, event, worklist);
+    }
+
+    fn run_activation(&mut self, node_idx: usize, event: &Event, worklist: &mut VecDeque<usize>) {
+        let mut context = NodeContext::new(self.nodes[node_idx].id.clone(), self.variables.clone());
+        match self.nodes[node_idx].behavior.on_activated(&mut context) {
+            Ok(request) => {
+                self.apply_context(context);
+                self.retries.remove(&self.nodes[node_idx].id);
+                if let Some(request) = request {
+                    self.schedule_timer(node_idx, request);
+                }
+                self.schedule_deadlines(node_idx);
+                self.request_action(node_idx, ActionHook::Activated);
+                self.request_child(node_idx);
+                worklist.push_back(node_idx);
+            }
+            Err(error) => self.hook_failed(node_idx, event, error, RetryHook::Activated, worklist),
+        }
+    }
+
+    /// Runs the completion hook before any target starts, so a node that
+    /// fails to complete does not hand over to its successors.
+    fn run_completion(
+        &mut self,
+        node_idx: usize,
+        event: &Event,
+        targets: Vec<(usize, Option<serde_json::Value>)>,
+        worklist: &mut VecDeque<usize>,
+    ) {
+        let hook = RetryHook::Completed {
+            targets: targets
+                .iter()
+                .map(|(idx, _)| self.nodes[*idx].id.clone())
+                .collect(),
+        };
+        if !self.run_completion_hook(node_idx, event, hook, worklist) {
+            return;
+        }
+        if self.activate_all(targets, event, worklist) {
+            self.complete_node(node_idx, event);
+            self.wake_joins(node_idx, worklist);
+        }
+    }
+
+    /// Queues active nodes whose joins wait on a node that just finished, so
+    /// a join passes on the event its last branch arrives on even if its
+    /// node was evaluated earlier.
+    fn wake_joins(&self, node_idx: usize, worklist: &mut VecDeque<usize>) {
+        let Some(waiters) = self.join_waiters.get(&self.nodes[node_idx].id) else {
+            return;
+        };
+        for &i in waiters {
+            if self.nodes[i].status == NodeStatus::Active && !worklist.contains(&i) {
+                worklist.push_back(i);
+            }
+        }
+    }
+
+    /// Maps each node joins wait on to the nodes whose edges wait on it.
+    fn index_join_waiters(&mut self) {
+        self.join_waiters.clear();
+        for (i, node) in self.nodes.iter().enumerate() {
+            for edge in &node.edges {
+                for waits_on in edge.gate.waited_nodes() {
+                    let waiters = self.join_waiters.entry(waits_on).or_default();
+                    if !waiters.contains(&i) {
+                        waiters.push(i);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Runs the completion hook of a node and keeps what it wrote. A failing
+    /// hook is handed to the node's retry policy, to be resumed as `hook`.
+    fn run_completion_hook(
+        &mut self,
+        node_idx: usize,
+        event: &Event,
+        hook: RetryHook,
+        worklist: &mut VecDeque<usize>,
+    ) -> bool {
+        let mut context = NodeContext::new(self.nodes[node_idx].id.clone(), self.variables.clone());
+        if let Err(error) = self.nodes[node_idx].behavior.on_completed(&mut context) {
+            self.hook_failed(node_idx, event, error, hook, worklist);
+            return false;
+        }
+
+        self.apply_context(context);
+        self.retries.remove(&self.nodes[node_idx].id);
+        if self.nodes[node_idx].compensation.is_some() {
+            self.completion_order.push(self.nodes[node_idx].id.clone());
+        }
+        true
+    }
+
+    /// Keeps what a successful hook wrote to its context.
+    fn apply_context(&mut self, context: NodeContext) {
+        let (variables, outbox) = context.into_parts();
+        self.variables = variables;
+        self.outbox.extend(outbox);
+    }
+
+    fn retry_hook(&mut self, node_idx: usize, event: &Event, worklist: &mut VecDeque<usize>) {
+        let Some(state) = self.retries.get(&self.nodes[node_idx].id) else {
+            return;
+        };
+        match state.hook.clone() {
+            RetryHook::Activated => self.run_activation(node_idx, event, worklist),
+            RetryHook::Completed { targets } => {
+                let targets = targets
+                    .iter()
+                    .filter_map(|id| self.node_index(id))
+                    .filter(|&idx| self.nodes[idx].status == NodeStatus::NotStarted)
+                    .map(|idx| (idx, None))
+                    .collect();
+                self.run_completion(node_idx, event, targets, worklist);
+            }
+            RetryHook::Looped => {
+                let target = self.nodes[node_idx]
+                    .loop_back
+                    .as_ref()
+                    .and_then(|loop_back| self.node_index(&loop_back.target));
+                if let Some(target_idx) = target {
+                    self.run_loop_back(node_idx, target_idx, event, None, worklist);
+                }
+            }
+        }
+    }
+
+    /// Schedules another attempt of a failed hook if the node's retry policy
+    /// allows one, and fails the node otherwise.
+    fn hook_failed(
+        &mut self,
+        node_idx: usize,
+        event: &Event,
+        error: BehaviorError,
+        hook: RetryHook,
+        worklist: &mut VecDeque<usize>,
+    ) {
+        let node_id = self.nodes[node_idx].id.clone();
+        let attempts = self.retries.remove(&node_id).map_or(0, |s| s.attempts) + 1;
+
+        if let Some(policy) = &self.nodes[node_idx].retry {
+            if policy.allows_retry(attempts) {
+                let timer_id = format!("retry:{}:{}", node_id, attempts);
+                let delay = policy.backoff(attempts, &format!("{}:{}", self.id, timer_id));
+                self.schedule_timer(node_idx, TimerRequest::new(timer_id.clone(), delay));
+                self.retries.insert(
+                    node_id,
+                    RetryState {
+                        hook,
+                        attempts,
+                        last_error: error.0,
+                        timer_id,
+                    },
+                );
+                return;
+            }
+        }
+
+        self.fail_node(node_idx, event, error, worklist);
+    }
+
+    fn set_status(
+        &mut self,
+        node_idx: usize,
+        status: NodeStatus,
+        event: &Event,
+        gate: Option<serde_json::Value>,
+        error: Option<String>,
+    ) {
+        let now = self.now();
+        let node = &mut self.nodes[node_idx];
+        self.journal.push(Transition {
+            node_id: node.id.clone(),
+            from: node.status,
+            to: status,
+            event: event.clone(),
+            gate,
+            error,
+            at: now,
+        });
+        match status {
+            NodeStatus::Active => {
+                node.activated_at = Some(now);
+                node.completed_at = None;
+            }
+            NodeStatus::Completed => node.completed_at = Some(now),
+            NodeStatus::NotStarted
+            | NodeStatus::Failed
+            | NodeStatus::Compensated
+            | NodeStatus::Cancelled => {}
+        }
+        node.status = status;
+    }
+
+    /// Marks a node completed once its completion hook has succeeded.
+    fn complete_node(&mut self, node_idx: usize, event: &Event) {
+        self.set_status(node_idx, NodeStatus::Completed, event, None, None);
+        self.cancel_timers(node_idx);
+        self.request_action(node_idx, ActionHook::Completed);
+    }
+
+    fn request_action(&mut self, node_idx: usize, hook: ActionHook) {
+        let node = &self.nodes[node_idx];
+        if !node
+            .action
+            .as_ref()
+            .is_some_and(|action| action.runs_on(hook))
+        {
+            return;
+        }
+        self.action_seq += 1;
+        let action_id = format!("{}:{}:{}", node.id, hook, self.action_seq);
+        self.pending_actions
+            .insert(node.id.clone(), action_id.clone());
+        self.action_requests.push(ActionRequest {
+            action_id,
+            node_id: node.id.clone(),
+            hook,
+        });
+    }
+
+    fn request_child(&mut self, node_idx: usize) {
+        let node = &self.nodes[node_idx];
+        let Some(sub_workflow) = &node.sub_workflow else {
+            return;
+        };
+        let child_id = Uuid::new_v4();
+        self.children.insert(node.id.clone(), child_id);
+        self.child_requests.push(ChildRequest {
+            child_id,
+            node_id: node.id.clone(),
+            definition: sub_workflow.definition.clone(),
+        });
+    }
+
+    /// Cancels the children still running and tells the parent, if any,
+    /// that this workflow has finished.
+    fn finish(&mut self) {
+        self.child_cancellations
+            .extend(self.children.drain().map(|(_, child_id)| child_id));
+        if let Some(parent) = &self.parent {
+            self.parent_notification = Some(Event::ChildFinished {
+                node_id: parent.node_id.clone(),
+                child_id: self.id,
+                status: self.status,
+                reason: match self.status {
+                    WorkflowStatus::Failed => self.failure.as_ref().map(|f| f.to_string()),
+                    WorkflowStatus::Cancelled => self.cancel_reason.clone(),
+                    WorkflowStatus::Active | WorkflowStatus::Completed => None,
+                },
+            });
+        }
+    }
+
+    /// Marks a node failed and applies its failure policy.
+    fn fail_node(
+        &mut self,
+        node_idx: usize,
+        event: &Event,
+        error: BehaviorError,
+        worklist: &mut VecDeque<usize>,
+    ) {
+        self.set_status(
+            node_idx,
+            NodeStatus::Failed,
+            event,
+            None,
+            Some(error.0.clone()),
+        );
+        self.cancel_timers(node_idx);
+        self.wake_joins(node_idx, worklist);
+
+        match self.nodes[node_idx].on_failure.clone() {
+            FailurePolicy::FailWorkflow => {
+                self.status = WorkflowStatus::Failed;
+                self.failure = Some(NodeFailure {
+                    node_id: self.nodes[node_idx].id.clone(),
+                    reason: error.0,
+                });
+                worklist.clear();
+                self.finish();
+                self.start_compensation(event);
+            }
+            FailurePolicy::Continue => {
+                if self.failure.is_none() {
+                    self.failure = Some(NodeFailure {
+                        node_id: self.nodes[node_idx].id.clone(),
+                        reason: error.0,
+                    });
+                }
+            }
+            FailurePolicy::ErrorEdge(target) => {
+                if let Some(target_idx) = self.node_index(&target) {
+                    if self.nodes[target_idx].status == NodeStatus::NotStarted {
+                        self.activate(target_idx, event, None, worklist);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stops an active workflow. Active nodes and nodes waiting to retry are
+    /// cancelled along with their timers, and nothing runs afterwards.
+    /// Outbox messages not yet delivered are cancelled when the workflow is
+    /// saved.
+    pub fn cancel(&mut self, reason: impl Into<String>) {
+        let event = Event::Cancelled {
+            reason: reason.into(),
+        };
+        // Cancelling never takes a step, so it cannot hit the step limit
+        let _ = self.process_event(&event);
+    }
+
+    fn cancel_nodes(&mut self, event: &Event, reason: &str) {
+        if self.status != WorkflowStatus::Active {
+            return;
+        }
+        for node_idx in 0..self.nodes.len() {
+            if self.nodes[node_idx].status == NodeStatus::Active {
+                self.set_status(
+                    node_idx,
+                    NodeStatus::Cancelled,
+                    event,
+                    None,
+                    Some(reason.to_string()),
+                );
+            }
+            self.cancel_timers(node_idx);
+        }
+        self.retries.clear();
+        self.status = WorkflowStatus::Cancelled;
+        self.cancel_reason = Some(reason.to_string());
+        self.outbox_cancelled = true;
+        self.finish();
+        self.start_compensation(event);
+    }
+
+    /// Compensates completed nodes, most recently completed first. A node
+    /// whose successor failed the workflow while being activated is still
+    /// `Active`, but has done its work and is compensated as well.
+    fn start_compensation(&mut self, event: &Event) {
+        let pending: Vec<_> = self
+            .completion_order
+            .iter()
+            .rev()
+            .filter(|id| {
+                self.node(id)
+                    .is_some_and(|n| matches!(n.status, NodeStatus::Completed | NodeStatus::Active))
+            })
+            .cloned()
+            .collect();
+        if pending.is_empty() {
+            return;
+        }
+        self.compensation = Some(CompensationState {
+            pending,
+            ..CompensationState::default()
+        });
+        self.run_compensations(event);
+    }
+
+    fn resume_compensation(&mut self, event: &Event) {
+        let Event::Timer { timer_id } = event else {
+            return;
+        };
+        let Some(state) = self
+            .compensation
+            .as_mut()
+            .filter(|state| state.timer_id.as_ref() == Some(timer_id))
+        else {
+            return;
+        };
+        state.timer_id = None;
+        self.scheduled_timers.retain(|t| t.timer_id != *timer_id);
+        self.run_compensations(event);
+    }
+
+    /// Runs pending compensations in order. A failing compensation is
+    /// retried under its node's retry policy, which pauses the remaining
+    /// ones until the retry timer fires, and is given up on once the policy
+    /// is exhausted.
+    fn run_compensations(&mut self, event: &Event) {
+        let Some(mut state) = self.compensation.take() else {
+            return;
+        };
+
+        while let Some(node_id) = state.pending.first().cloned() {
+            let Some(node_idx) = self.node_index(&node_id) else {
+                state.pending.remove(0);
+                continue;
+            };
+            let mut context = NodeContext::new(node_id.clone(), self.variables.clone());
+            let result = match &self.nodes[node_idx].compensation {
+                Some(compensation) => compensation.compensate(&mut context),
+                None => Ok(()),
+            };
+
+            match result {
+                Ok(()) => {
+                    self.apply_context(context);
+                    self.set_status(node_idx, NodeStatus::Compensated, event, None, None);
+                    state.pending.remove(0);
+                    state.compensated.push(node_id);
+                    state.attempts = 0;
+                }
+                Err(error) => {
+                    state.attempts += 1;
+                    if let Some(policy) = &self.nodes[node_idx].retry {
+                        if policy.allows_retry(state.attempts) {
+                            let timer_id = format!("compensate:{}:{}", node_id, state.attempts);
+                            let delay = policy
+                                .backoff(state.attempts, &format!("{}:{}", self.id, timer_id));
+                            self.schedule_timer(
+                                node_idx,
+                                TimerRequest::new(timer_id.clone(), delay),
+                            );
+                            state.timer_id = Some(timer_id);
+                            break;
+                        }
+                    }
+                    state.pending.remove(0);
+                    state.failed.push((node_id, error.0));
+                    state.attempts = 0;
+                }
+            }
+        }
+
+        self.compensation = Some(state);
+    }
+
+    /// Timers owned by a node that left Active some other way must not fire.
+    fn cancel_timers(&mut self, node_idx: usize) {
+        let node_id = self.nodes[node_idx].id.clone();
+        let pending = self.scheduled_timers.len();
+        self.scheduled_timers.retain(|t| t.node_id != node_id);
+        if self.scheduled_timers.len() != pending {
+            self.timer_commands.push(TimerCommand::Cancel { node_id });
+        }
+    }
+
+    /// Schedules a timer for each time limit on the node's edges, its
+    /// loop-back edge included, measured from when the node was activated,
+    /// so deadlines pass without waiting for another event.
+    fn schedule_deadlines(&mut self, node_idx: usize) {
+        let node = &self.nodes[node_idx];
+        let mut limits: Vec<_> = node
+            .edges
+            .iter()
+            .map(|edge| &edge.gate)
+            .chain(node.loop_back.as_ref().map(|loop_back| &loop_back.gate))
+            .flat_map(|gate| gate.time_limits())
+            .collect();
+        if limits.is_empty() {
+            return;
+        }
+        limits.sort();
+        limits.dedup();
+
+        let activated_at = node.activated_at.unwrap_or_else(|| self.now());
+        let elapsed = self.now() - activated_at;
+        for limit in limits {
+            let timer_id = deadline_timer_id(&self.nodes[node_idx].id, limit);
+            if self.scheduled_timers.iter().any(|t| t.timer_id == timer_id) {
+                continue;
+            }
+            let delay = (limit - elapsed).try_into().unwrap_or_default();
+            self.schedule_timer(node_idx, TimerRequest::new(timer_id, delay));
+        }
+    }
+
+    fn now(&self) -> OffsetDateTime {
+        self.clock.unwrap_or_else(OffsetDateTime::now_utc)
+    }
+
+    fn schedule_timer(&mut self, node_idx: usize, request: TimerRequest) {
+        let timer = ScheduledTimer {
+            timer_id: request.timer_id,
+            node_id: self.nodes[node_idx].id.clone(),
+            fire_at: self.now() + request.delay,
+        };
+        self.scheduled_timers.push(timer.clone());
+        self.timer_commands.push(TimerCommand::Schedule(timer));
+    }
+
+    /// Encodes the workflow as `BLOB_MAGIC`, `BLOB_VERSION` and the body.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BlobError> {
+        let mut bytes = BLOB_MAGIC.to_vec();
+        bytes.push(BLOB_VERSION);
+        serde_json::to_writer(&mut bytes, self)?;
+        Ok(bytes)
+    }
+
+    /// Decodes a blob written by `to_bytes`, or by the unversioned bincode
+    /// encoding used before it, which is read into the frozen layout of that
+    /// time and converted. Rows in the old encoding are rewritten by the
+    /// migrate binary.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, BlobError> {
+        let mut workflow: Self = match bytes.strip_prefix(BLOB_MAGIC) {
+            Some([BLOB_VERSION, body @ ..]) => serde_json::from_slice(body)?,
+            Some([version, ..]) => return Err(BlobError::UnsupportedVersion(*version)),
+            Some([]) => return Err(BlobError::UnsupportedVersion(0)),
+            None => Self::legacy_options()
+                .deserialize::<LegacyWorkflow>(bytes)?
+                .into(),
+        };
+        workflow.reindex();
+        Ok(workflow)
     }
 
-    pub fn from_bytes(bytes: &[u8]) -> Result<Self, bincode::Error> {
-        let config = DefaultOptions::new()
+    fn legacy_options() -> impl Options {
+        DefaultOptions::new()
             .with_fixint_encoding()
             .allow_trailing_bytes()
             .with_native_endian()
-            .with_no_limit();
-        config.deserialize(bytes)
+            .with_no_limit()
     }
 }
@@ -0,0 +1,183 @@
+use crate::models::action::{ActionContext, ActionResult, NodeAction};
+use crate::models::{workflow::WorkflowStatus, Event};
+use crate::workflow::dispatcher::{
+    apply, retry_on_conflict, DispatchOutcome, DEFAULT_MAX_ATTEMPTS,
+};
+use crate::workflow::storage::{
+    error::StorageError, ActionRepository, EventRepository, PendingAction, WorkflowRepository,
+};
+use crate::workflow::PostgresStorage;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+
+/// Background task that runs the node actions workflows requested and feeds
+/// each outcome back to its workflow as an `Event::Action`.
+pub struct ActionRunner {
+    storage: PostgresStorage,
+    poll_interval: Duration,
+    lease: Duration,
+    batch_size: i64,
+    max_attempts: usize,
+}
+
+impl ActionRunner {
+    pub fn new(storage: PostgresStorage) -> Self {
+        Self {
+            storage,
+            poll_interval: Duration::from_secs(1),
+            lease: Duration::from_secs(300),
+            batch_size: 10,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// How long a claimed action is hidden from other runners. Keep it longer
+    /// than the slowest action timeout, or the action may run twice; the
+    /// outcome of a run that outlives its lease is discarded.
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// How many actions one poll runs at most.
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.run_pending_actions().await {
+                eprintln!("Action runner error: {}", e);
+            }
+        }
+    }
+
+    /// Runs up to a batch of pending actions and returns how many were
+    /// handled. Each action is claimed on its own just before it runs, so
+    /// the lease only has to cover one action. An action whose outcome could
+    /// not be recorded is run again once its lease has passed.
+    pub async fn run_pending_actions(&self) -> Result<usize, StorageError> {
+        let mut count = 0;
+        for _ in 0..self.batch_size {
+            let Some(action) = self
+                .storage
+                .claim_pending_action(OffsetDateTime::now_utc(), self.lease)
+                .await?
+            else {
+                break;
+            };
+            count += 1;
+            if let Err(e) = self.execute(&action).await {
+                eprintln!(
+                    "Action {} of workflow {} could not be recorded: {}",
+                    action.action_id, action.workflow_id, e
+                );
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Runs one action outside any transaction, then records its outcome and
+    /// marks it done together.
+    async fn execute(&self, pending: &PendingAction) -> Result<(), StorageError> {
+        let workflow = self
+            .storage
+            .load_workflow(pending.user_id, pending.workflow_id)
+            .await?;
+        let action = workflow
+            .as_ref()
+            .filter(|w| w.status == WorkflowStatus::Active)
+            .and_then(|w| w.node(&pending.node_id))
+            .and_then(|node| node.action.as_deref());
+
+        let event = match action {
+            Some(action) => {
+                let context = ActionContext {
+                    workflow_id: pending.workflow_id,
+                    user_id: pending.user_id,
+                    node_id: pending.node_id.clone(),
+                    action_id: pending.action_id.clone(),
+                    hook: pending.hook,
+                };
+                Some(Event::Action {
+                    node_id: pending.node_id.clone(),
+                    action_id: pending.action_id.clone(),
+                    result: run_action(action, &context).await,
+                })
+            }
+            None => None,
+        };
+
+        retry_on_conflict(self.max_attempts, || self.deliver(pending, event.as_ref())).await
+    }
+
+    async fn deliver(
+        &self,
+        pending: &PendingAction,
+        event: Option<&Event>,
+    ) -> Result<(), StorageError> {
+        let uow = self.storage.begin().await?;
+
+        if let Some(event) = event {
+            let workflow = uow
+                .load_workflow(pending.user_id, pending.workflow_id)
+                .await?;
+            if let Some(workflow) = workflow.filter(|w| w.status == WorkflowStatus::Active) {
+                uow.save_event(
+                    pending.user_id,
+                    Some(workflow.id),
+                    workflow.correlation_key.as_ref(),
+                    event,
+                )
+                .await?;
+                if let DispatchOutcome::Failed(reason) = apply(&uow, workflow, event).await? {
+                    eprintln!(
+                        "Action {} could not be applied to workflow {}: {}",
+                        pending.action_id, pending.workflow_id, reason
+                    );
+                }
+            }
+        }
+
+        if !uow
+            .mark_action_done(pending.id, pending.lease_token)
+            .await?
+        {
+            eprintln!(
+                "Action {} of workflow {} was claimed by another runner before its outcome was recorded",
+                pending.action_id, pending.workflow_id
+            );
+            return uow.rollback().await;
+        }
+        uow.commit().await
+    }
+}
+
+/// Runs an action within its timeout and turns the outcome into the result
+/// delivered to the workflow.
+pub async fn run_action(action: &dyn NodeAction, context: &ActionContext) -> ActionResult {
+    match tokio::time::timeout(action.timeout(), action.run(context)).await {
+        Ok(Ok(value)) => ActionResult::Succeeded(value),
+        Ok(Err(error)) => ActionResult::Failed(error.to_string()),
+        Err(_) => ActionResult::TimedOut,
+    }
+}
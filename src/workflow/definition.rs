@@ -0,0 +1,305 @@
+//! Declarative JSON format for authoring workflows without recompiling.
+//!
+//! A definition lists the nodes of the graph. Behaviors and gate conditions
+//! are referenced by the name they are registered under with `typetag`, so
+//! any `NodeBehavior` or `Condition` compiled into the binary can be used:
+//!
+//! ```json
+//! {
+//!   "name": "user_activity",
+//!   "nodes": [
+//!     {
+//!       "id": "start",
+//!       "name": "User Activity",
+//!       "initial": true,
+//!       "behavior": { "type": "EmptyBehavior" },
+//!       "edges": [
+//!         { "target": "finish", "gate": { "Single": { "type": "UserActivityCondition" } } }
+//!       ]
+//!     },
+//!     {
+//!       "id": "finish",
+//!       "name": "Finish",
+//!       "behavior": { "type": "FinishNodeBehavior" }
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! Node ids are arbitrary unique strings that edges and joins refer to.
+//! Nodes marked `initial` start out `Active`, every other node starts out
+//! `NotStarted`. `edges` may be omitted for terminal nodes.
+//!
+//! # Gates
+//!
+//! A gate is one of `{"Single": <condition>}`, `{"And": [<gate>, ...]}`,
+//! `{"Or": [<gate>, ...]}`, `{"Not": <gate>}`,
+//! `{"WaitForNodes": [<node id>, ...]}`, `{"Join": ...}`,
+//! `{"DeadlineElapsed": <duration>}` or `{"WithinWindow": <duration>}`. A
+//! join's `quorum` defaults to all of its nodes and `statuses` to
+//! `["Completed"]`. A condition is an object whose `type` names the
+//! condition followed by its fields, and simple predicates can be written
+//! inline as an `ExpressionCondition`, see `models::expression`:
+//!
+//! ```json
+//! { "And": [
+//!   { "Single": { "type": "TimerCondition", "timer_id": "1" } },
+//!   { "Join": { "nodes": ["a", "b"], "quorum": 1, "statuses": ["Completed", "Failed"] } },
+//!   { "Single": { "type": "ExpressionCondition", "expression": "event.amount > 100" } }
+//! ] }
+//! ```
+//!
+//! # Durations
+//!
+//! Durations are written as a number and a unit, one of `ns`, `us`, `ms`,
+//! `s`, `m`, `h` or `d`, or as a plain number of milliseconds, so
+//! `"90s"`, `"1500ms"` and `1500` are all valid:
+//!
+//! ```json
+//! { "DeadlineElapsed": "3d" }
+//! ```
+//!
+//! # Failure policy
+//!
+//! `on_failure` decides what a failing behavior does to the workflow:
+//! `"FailWorkflow"` (the default) fails it, `"Continue"` only fails the node
+//! and lets the other branches carry on, and `ErrorEdge` activates a
+//! fallback node instead:
+//!
+//! ```json
+//! { "id": "charge", "name": "Charge", "behavior": { "type": "ChargeCard" },
+//!   "on_failure": { "ErrorEdge": "notify_support" } }
+//! ```
+//!
+//! # Retry
+//!
+//! `retry` retries a failing behavior before its failure policy applies.
+//! The delay starts at `initial_backoff` and is multiplied by `multiplier`
+//! (default 2) after each attempt, up to `max_backoff` (default `"1h"`),
+//! with a `jitter` fraction (default 0.2) added or removed:
+//!
+//! ```json
+//! "retry": { "max_attempts": 3, "initial_backoff": "5s", "max_backoff": "1m" }
+//! ```
+//!
+//! # Actions
+//!
+//! `action` names an asynchronous `NodeAction`, registered with `typetag`
+//! like behaviors, that runs in the background and reports back with an
+//! `Event::Action`:
+//!
+//! ```json
+//! "action": { "type": "SendWelcomeEmail", "template": "welcome" }
+//! ```
+//!
+//! # Compensation
+//!
+//! `compensation` names a `Compensation` that undoes the node's work if the
+//! workflow later fails or is cancelled. Completed nodes are compensated in
+//! reverse order of completion:
+//!
+//! ```json
+//! "compensation": { "type": "RefundPayment" }
+//! ```
+//!
+//! # Sub-workflows
+//!
+//! `sub_workflow` starts a child workflow from the latest version of a
+//! stored definition and holds the node until the child finishes. Gate the
+//! node's edges on `SubWorkflowCompleted` to continue as soon as it
+//! completes:
+//!
+//! ```json
+//! { "id": "onboard", "name": "Onboard", "behavior": { "type": "EmptyBehavior" },
+//!   "sub_workflow": { "definition": "onboarding" },
+//!   "edges": [
+//!     { "target": "done", "gate": { "Single": { "type": "SubWorkflowCompleted", "node_id": "onboard" } } }
+//!   ] }
+//! ```
+//!
+//! # Loop-backs
+//!
+//! `loop_back` is an edge back to an upstream node that runs the nodes in
+//! between again, at most `max_iterations` times before leading to `exit`
+//! instead:
+//!
+//! ```json
+//! "loop_back": { "target": "nudge", "gate": { "DeadlineElapsed": "3d" },
+//!                "max_iterations": 5, "exit": "give_up" }
+//! ```
+
+use crate::models::{
+    action::NodeAction,
+    compensation::Compensation,
+    edge::{Edge, LoopBack},
+    node::{FailurePolicy, NodeBehavior, NodeId},
+    retry::RetryPolicy,
+    sub_workflow::SubWorkflow,
+    workflow::DefinitionRef,
+    Node, NodeStatus, ValidationError, Workflow,
+};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use time::OffsetDateTime;
+
+#[derive(Error, Debug)]
+pub enum DefinitionError {
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Invalid workflow definition: {0:?}")]
+    Invalid(Vec<ValidationError>),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct WorkflowDefinition {
+    pub name: String,
+    pub nodes: Vec<NodeDefinition>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NodeDefinition {
+    pub id: NodeId,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub initial: bool,
+    pub behavior: Box<dyn NodeBehavior>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub edges: Vec<Edge>,
+    #[serde(default, skip_serializing_if = "is_default_policy")]
+    pub on_failure: FailurePolicy,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retry: Option<RetryPolicy>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub action: Option<Box<dyn NodeAction>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compensation: Option<Box<dyn Compensation>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sub_workflow: Option<SubWorkflow>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub loop_back: Option<LoopBack>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+fn is_default_policy(policy: &FailurePolicy) -> bool {
+    *policy == FailurePolicy::default()
+}
+
+impl WorkflowDefinition {
+    pub fn from_json(json: &str) -> Result<Self, DefinitionError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    pub fn to_json(&self) -> Result<String, DefinitionError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Instantiates a new workflow from the definition, rejecting graphs
+    /// that do not validate.
+    pub fn into_workflow(self) -> Result<Workflow, DefinitionError> {
+        self.into_workflow_at(OffsetDateTime::now_utc())
+    }
+
+    /// Like `into_workflow`, for an instance created at `created_at`, see
+    /// `Workflow::new_at`.
+    pub fn into_workflow_at(self, created_at: OffsetDateTime) -> Result<Workflow, DefinitionError> {
+        let nodes = self
+            .nodes
+            .into_iter()
+            .map(|node| Node {
+                id: node.id,
+                name: node.name,
+                status: if node.initial {
+                    NodeStatus::Active
+                } else {
+                    NodeStatus::NotStarted
+                },
+                edges: node.edges,
+                behavior: node.behavior,
+                on_failure: node.on_failure,
+                retry: node.retry,
+                action: node.action,
+                compensation: node.compensation,
+                sub_workflow: node.sub_workflow,
+                loop_back: node.loop_back,
+                activated_at: None,
+                completed_at: None,
+            })
+            .collect();
+
+        let mut workflow = Workflow::new_at(nodes, created_at);
+        workflow.validate().map_err(DefinitionError::Invalid)?;
+        workflow.name = self.name;
+        Ok(workflow)
+    }
+
+    /// Like `into_workflow`, but records the stored version the instance
+    /// runs so it can later be migrated to a newer one.
+    pub fn instantiate(self, version: DefinitionRef) -> Result<Workflow, DefinitionError> {
+        let mut workflow = self.into_workflow()?;
+        workflow.definition = Some(version);
+        Ok(workflow)
+    }
+}
+
+/// Borrowed mirror of `WorkflowDefinition` used to write existing workflows.
+#[derive(Serialize)]
+struct WorkflowDefinitionRef<'a> {
+    name: &'a str,
+    nodes: Vec<NodeDefinitionRef<'a>>,
+}
+
+#[derive(Serialize)]
+struct NodeDefinitionRef<'a> {
+    id: &'a NodeId,
+    name: &'a str,
+    #[serde(skip_serializing_if = "is_false")]
+    initial: bool,
+    behavior: &'a dyn NodeBehavior,
+    #[serde(skip_serializing_if = "<[Edge]>::is_empty")]
+    edges: &'a [Edge],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on_failure: Option<&'a FailurePolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    retry: Option<&'a RetryPolicy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    action: Option<&'a dyn NodeAction>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    compensation: Option<&'a dyn Compensation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sub_workflow: Option<&'a SubWorkflow>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    loop_back: Option<&'a LoopBack>,
+}
+
+pub fn load_workflow(json: &str) -> Result<Workflow, DefinitionError> {
+    WorkflowDefinition::from_json(json)?.into_workflow()
+}
+
+/// Writes the graph of a workflow in the definition format. Nodes that are
+/// currently `Active` are written as the initial nodes.
+pub fn write_workflow(workflow: &Workflow) -> Result<String, DefinitionError> {
+    let definition = WorkflowDefinitionRef {
+        name: &workflow.name,
+        nodes: workflow
+            .nodes
+            .iter()
+            .map(|node| NodeDefinitionRef {
+                id: &node.id,
+                name: &node.name,
+                initial: node.status == NodeStatus::Active,
+                behavior: node.behavior.as_ref(),
+                edges: &node.edges,
+                on_failure: Some(&node.on_failure).filter(|p| !is_default_policy(p)),
+                retry: node.retry.as_ref(),
+                action: node.action.as_deref(),
+                compensation: node.compensation.as_deref(),
+                sub_workflow: node.sub_workflow.as_ref(),
+                loop_back: node.loop_back.as_ref(),
+            })
+            .collect(),
+    };
+    Ok(serde_json::to_string_pretty(&definition)?)
+}
@@ -0,0 +1,451 @@
+use crate::models::{
+    sub_workflow::ParentLink, workflow::WorkflowStatus, CorrelationKey, Event, Workflow,
+};
+use crate::workflow::storage::{
+    error::StorageError, ActionRepository, DefinitionRepository, EventRepository, OutboxRepository,
+    TimerRepository, TransitionRepository, WorkflowRepository,
+};
+use crate::workflow::PostgresStorage;
+use std::collections::HashSet;
+use std::future::Future;
+use uuid::Uuid;
+
+/// How many times a dispatch is attempted before a version conflict is
+/// returned to the caller.
+pub const DEFAULT_MAX_ATTEMPTS: usize = 3;
+
+/// What happened to one workflow when an event was dispatched to it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DispatchOutcome {
+    Unchanged,
+    Changed,
+    Completed,
+    Failed(String),
+    Cancelled,
+}
+
+#[derive(Debug, Default)]
+pub struct DispatchReport {
+    pub outcomes: Vec<(Uuid, DispatchOutcome)>,
+}
+
+impl DispatchReport {
+    pub fn changed(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.with_outcome(|o| *o == DispatchOutcome::Changed)
+    }
+
+    pub fn completed(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.with_outcome(|o| *o == DispatchOutcome::Completed)
+    }
+
+    pub fn cancelled(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.with_outcome(|o| *o == DispatchOutcome::Cancelled)
+    }
+
+    pub fn failed(&self) -> impl Iterator<Item = (Uuid, &str)> + '_ {
+        self.outcomes
+            .iter()
+            .filter_map(|(id, outcome)| match outcome {
+                DispatchOutcome::Failed(reason) => Some((*id, reason.as_str())),
+                _ => None,
+            })
+    }
+
+    fn with_outcome(
+        &self,
+        predicate: impl Fn(&DispatchOutcome) -> bool + 'static,
+    ) -> impl Iterator<Item = Uuid> + '_ {
+        self.outcomes
+            .iter()
+            .filter(move |(_, outcome)| predicate(outcome))
+            .map(|(id, _)| *id)
+    }
+}
+
+/// Routes a user's events to every active workflow of that user, or only to
+/// those started with a correlation key, and persists the resulting state.
+#[derive(Clone)]
+pub struct Dispatcher {
+    storage: PostgresStorage,
+    max_attempts: usize,
+}
+
+impl Dispatcher {
+    pub fn new(storage: PostgresStorage) -> Self {
+        Self {
+            storage,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    /// Records the event and applies it to each of the user's active
+    /// workflows in a single transaction, so the event is never stored
+    /// without its effects or the other way around. A workflow whose event
+    /// processing fails does not stop the others; its failure is reported and
+    /// its stored state is left untouched.
+    ///
+    /// If another process saves one of the workflows first, the transaction
+    /// is rolled back and the whole dispatch runs again on fresh state.
+    pub async fn dispatch(
+        &self,
+        user_id: Uuid,
+        event: &Event,
+    ) -> Result<DispatchReport, StorageError> {
+        retry_on_conflict(self.max_attempts, || {
+            self.dispatch_once(user_id, None, event)
+        })
+        .await
+    }
+
+    /// Like `dispatch`, but only the user's active workflows started with
+    /// `correlation_key` see the event, and it is stored with the key so a
+    /// replay delivers it the same way.
+    pub async fn dispatch_correlated(
+        &self,
+        user_id: Uuid,
+        correlation_key: &CorrelationKey,
+        event: &Event,
+    ) -> Result<DispatchReport, StorageError> {
+        retry_on_conflict(self.max_attempts, || {
+            self.dispatch_once(user_id, Some(correlation_key), event)
+        })
+        .await
+    }
+
+    /// Cancels one workflow, along with its pending timers and outbox
+    /// entries. Returns `false` if it was not active. The cancellation is
+    /// recorded as an event of that workflow, so replaying it cancels the
+    /// workflow again.
+    pub async fn cancel_workflow(
+        &self,
+        user_id: Uuid,
+        workflow_id: Uuid,
+        reason: &str,
+    ) -> Result<bool, StorageError> {
+        let event = Event::Cancelled {
+            reason: reason.to_string(),
+        };
+        retry_on_conflict(self.max_attempts, || async {
+            let uow = self.storage.begin().await?;
+            let Some(mut workflow) = uow
+                .load_workflow(user_id, workflow_id)
+                .await?
+                .filter(|w| w.status == WorkflowStatus::Active)
+            else {
+                return Ok(false);
+            };
+            uow.save_event(
+                user_id,
+                Some(workflow_id),
+                workflow.correlation_key.as_ref(),
+                &event,
+            )
+            .await?;
+            workflow.cancel(reason);
+            save(&uow, &mut workflow).await?;
+            uow.commit().await?;
+            Ok(true)
+        })
+        .await
+    }
+
+    /// Cancels every active workflow of a user and returns how many were
+    /// cancelled. The cancellation is recorded as an event of each of them,
+    /// so replaying one cancels it again while workflows started later are
+    /// not affected.
+    pub async fn cancel_user_workflows(
+        &self,
+        user_id: Uuid,
+        reason: &str,
+    ) -> Result<usize, StorageError> {
+        let event = Event::Cancelled {
+            reason: reason.to_string(),
+        };
+        retry_on_conflict(self.max_attempts, || async {
+            let uow = self.storage.begin().await?;
+            let mut workflows = uow.get_active_workflows_for_user(user_id).await?;
+            let count = workflows.len();
+            for workflow in &workflows {
+                uow.save_event(
+                    user_id,
+                    Some(workflow.id),
+                    workflow.correlation_key.as_ref(),
+                    &event,
+                )
+                .await?;
+            }
+            // Parents go first and cancel their children, rather than
+            // failing when a child is cancelled under them
+            workflows.sort_by_key(|w| w.parent.is_some());
+            let mut linked = HashSet::new();
+            for mut workflow in workflows {
+                if linked.contains(&workflow.id) {
+                    continue;
+                }
+                workflow.cancel(reason);
+                linked.extend(save(&uow, &mut workflow).await?);
+            }
+            uow.commit().await?;
+            Ok(count)
+        })
+        .await
+    }
+
+    async fn dispatch_once(
+        &self,
+        user_id: Uuid,
+        correlation_key: Option<&CorrelationKey>,
+        event: &Event,
+    ) -> Result<DispatchReport, StorageError> {
+        let uow = self.storage.begin().await?;
+        uow.save_event(user_id, None, correlation_key, event)
+            .await?;
+        let mut workflows = match correlation_key {
+            Some(key) => {
+                uow.get_active_workflows_for_correlation(user_id, key)
+                    .await?
+            }
+            None => uow.get_active_workflows_for_user(user_id).await?,
+        };
+        // Parents go first, so a cancellation reaches each child from its
+        // parent rather than failing the parent through the child
+        workflows.sort_by_key(|w| w.parent.is_some());
+
+        let mut report = DispatchReport::default();
+        let mut linked = HashSet::new();
+        for workflow in workflows {
+            let workflow_id = workflow.id;
+            // A parent or child saved along with an earlier workflow must be
+            // read again
+            let workflow = if linked.contains(&workflow_id) {
+                match uow
+                    .load_workflow(user_id, workflow_id)
+                    .await?
+                    .filter(|w| w.status == WorkflowStatus::Active)
+                {
+                    Some(workflow) => workflow,
+                    None => continue,
+                }
+            } else {
+                workflow
+            };
+            let (outcome, saved) = apply_linked(&uow, workflow, event).await?;
+            report.outcomes.push((workflow_id, outcome));
+            linked.extend(saved);
+        }
+
+        uow.commit().await?;
+        Ok(report)
+    }
+}
+
+/// Runs `attempt` until it succeeds or fails with anything other than
+/// `StorageError::Conflict`, trying at most `max_attempts` times. Each attempt
+/// must start its own transaction so it sees the state that won the race.
+pub async fn retry_on_conflict<T, F, Fut>(
+    max_attempts: usize,
+    mut attempt: F,
+) -> Result<T, StorageError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, StorageError>>,
+{
+    let mut attempts = 1;
+    loop {
+        match attempt().await {
+            Err(StorageError::Conflict { .. }) if attempts < max_attempts => attempts += 1,
+            result => return result,
+        }
+    }
+}
+
+/// Applies an already recorded event to one workflow and saves the workflow
+/// along with the timers, actions, outbox messages, transitions and linked
+/// workflows it produced. Pass a unit of work to make the save part of a
+/// larger transaction.
+pub async fn apply<S>(
+    storage: &S,
+    workflow: Workflow,
+    event: &Event,
+) -> Result<DispatchOutcome, StorageError>
+where
+    S: WorkflowRepository
+        + TimerRepository
+        + ActionRepository
+        + OutboxRepository
+        + TransitionRepository
+        + DefinitionRepository
+        + EventRepository
+        + Sync,
+{
+    Ok(apply_linked(storage, workflow, event).await?.0)
+}
+
+/// Like `apply`, and also returns the other workflows saved, see `save`.
+async fn apply_linked<S>(
+    storage: &S,
+    mut workflow: Workflow,
+    event: &Event,
+) -> Result<(DispatchOutcome, Vec<Uuid>), StorageError>
+where
+    S: WorkflowRepository
+        + TimerRepository
+        + ActionRepository
+        + OutboxRepository
+        + TransitionRepository
+        + DefinitionRepository
+        + EventRepository
+        + Sync,
+{
+    if let Err(e) = workflow.process_event(event) {
+        return Ok((DispatchOutcome::Failed(e.to_string()), Vec::new()));
+    }
+
+    let outcome = match workflow.status {
+        WorkflowStatus::Completed => DispatchOutcome::Completed,
+        WorkflowStatus::Failed => DispatchOutcome::Failed(match &workflow.failure {
+            Some(failure) => failure.to_string(),
+            None => "workflow failed".to_string(),
+        }),
+        WorkflowStatus::Cancelled => DispatchOutcome::Cancelled,
+        WorkflowStatus::Active if workflow.journal().is_empty() => DispatchOutcome::Unchanged,
+        WorkflowStatus::Active => DispatchOutcome::Changed,
+    };
+
+    let saved = save(storage, &mut workflow).await?;
+    Ok((outcome, saved))
+}
+
+/// Saves a workflow and drains its pending timer commands, action requests,
+/// outbox messages and journal into storage. New workflows are saved this
+/// way too, so the deadlines and sub-workflows of their initial nodes start.
+///
+/// Child workflows it requested are started, children it left running are
+/// cancelled, and a child that finished other than by its parent cancelling
+/// it hands the parent its `Event::ChildFinished`, each saved the same way
+/// in turn. Returns the ids of the workflows it updated this way, whose
+/// earlier loaded copies are now stale.
+pub async fn save<S>(storage: &S, workflow: &mut Workflow) -> Result<Vec<Uuid>, StorageError>
+where
+    S: WorkflowRepository
+        + TimerRepository
+        + ActionRepository
+        + OutboxRepository
+        + TransitionRepository
+        + DefinitionRepository
+        + EventRepository
+        + Sync,
+{
+    let mut pending = save_one(storage, workflow).await?;
+    let mut saved = Vec::new();
+    while let Some((workflow_id, event)) = pending.pop() {
+        let Some(mut linked) = storage
+            .load_workflow(workflow.user_id, workflow_id)
+            .await?
+            .filter(|w| w.status == WorkflowStatus::Active)
+        else {
+            continue;
+        };
+        if let Event::ChildFinished { .. } = event {
+            storage
+                .save_event(
+                    workflow.user_id,
+                    Some(linked.id),
+                    linked.correlation_key.as_ref(),
+                    &event,
+                )
+                .await?;
+        }
+        if let Err(e) = linked.process_event(&event) {
+            eprintln!("Workflow {} could not be updated: {}", workflow_id, e);
+            continue;
+        }
+        // Only parents cancel linked workflows, and they have already let
+        // go of the child
+        if let Event::Cancelled { .. } = event {
+            linked.take_parent_notification();
+        }
+        pending.extend(save_one(storage, &mut linked).await?);
+        saved.push(workflow_id);
+    }
+    Ok(saved)
+}
+
+/// Saves one workflow and returns the events its linked workflows are due.
+async fn save_one<S>(
+    storage: &S,
+    workflow: &mut Workflow,
+) -> Result<Vec<(Uuid, Event)>, StorageError>
+where
+    S: WorkflowRepository
+        + TimerRepository
+        + ActionRepository
+        + OutboxRepository
+        + TransitionRepository
+        + DefinitionRepository
+        + Sync,
+{
+    storage.save_workflow(workflow).await?;
+    let commands = workflow.take_timer_commands();
+    storage.apply_timer_commands(workflow, &commands).await?;
+    let actions = workflow.take_action_requests();
+    storage.enqueue_actions(workflow, &actions).await?;
+    // Cancel earlier messages first, so those emitted by compensations
+    // still go out
+    if workflow.take_outbox_cancellation() {
+        storage.cancel_outbox_entries(workflow.id).await?;
+    }
+    let messages = workflow.take_outbox();
+    storage.enqueue_outbox(workflow, &messages).await?;
+    let transitions = workflow.take_journal();
+    storage.save_transitions(workflow.id, &transitions).await?;
+
+    let mut linked = Vec::new();
+    for request in workflow.take_child_requests() {
+        let child = match storage.latest_definition(&request.definition).await? {
+            Some((version, definition)) => {
+                definition.instantiate(version).map_err(|e| e.to_string())
+            }
+            None => Err(format!("definition {} not found", request.definition)),
+        };
+        match child {
+            Ok(mut child) => {
+                child.id = request.child_id;
+                child.user_id = workflow.user_id;
+                child.correlation_key = workflow.correlation_key.clone();
+                child.parent = Some(ParentLink {
+                    workflow_id: workflow.id,
+                    node_id: request.node_id,
+                });
+                // Starts the deadlines and sub-workflows of the child's
+                // initial nodes
+                linked.extend(Box::pin(save_one(storage, &mut child)).await?);
+            }
+            // The node fails as if its child had
+            Err(reason) => linked.push((
+                workflow.id,
+                Event::ChildFinished {
+                    node_id: request.node_id,
+                    child_id: request.child_id,
+                    status: WorkflowStatus::Failed,
+                    reason: Some(reason),
+                },
+            )),
+        }
+    }
+    for child_id in workflow.take_child_cancellations() {
+        let reason = format!("parent workflow {} {}", workflow.id, workflow.status);
+        linked.push((child_id, Event::Cancelled { reason }));
+    }
+    if let Some(event) = workflow.take_parent_notification() {
+        if let Some(parent) = &workflow.parent {
+            linked.push((parent.workflow_id, event));
+        }
+    }
+    Ok(linked)
+}
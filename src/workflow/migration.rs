@@ -0,0 +1,236 @@
+//! Moving running instances from one stored definition version to another.
+//!
+//! Every instance carries its own copy of the graph, so editing a definition
+//! only affects instances started afterwards. Migrating rebuilds an instance
+//! from the new version and carries node statuses over through an explicit
+//! `NodeMapping` from old node ids to new ones.
+
+use crate::models::{
+    node::NodeId,
+    retry::RetryHook,
+    workflow::{DefinitionRef, Workflow},
+    NodeStatus, ScheduledTimer, TimerCommand, ValidationError,
+};
+use crate::workflow::definition::{DefinitionError, WorkflowDefinition};
+use crate::workflow::dispatcher::save;
+use crate::workflow::storage::{
+    error::StorageError, ActionRepository, DefinitionRepository, EventRepository, OutboxRepository,
+    TimerRepository, TransitionRepository, WorkflowRepository,
+};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Definition error: {0}")]
+    Definition(#[from] DefinitionError),
+    #[error("Definition {0} not found")]
+    DefinitionNotFound(DefinitionRef),
+    #[error("Node {node} is {status} but has no mapping")]
+    UnmappedNode { node: NodeId, status: NodeStatus },
+    #[error("Node {node} is mapped to {target}, which the target definition does not have")]
+    UnknownTarget { node: NodeId, target: NodeId },
+    #[error("Migrated workflow is invalid: {0:?}")]
+    Invalid(Vec<ValidationError>),
+}
+
+/// Which node of the new version takes over the state of a node of the old
+/// one. Old nodes that have not started yet may be left out.
+#[derive(Debug, Clone, Default)]
+pub struct NodeMapping {
+    targets: HashMap<NodeId, NodeId>,
+}
+
+impl NodeMapping {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn map(mut self, from: impl Into<NodeId>, to: impl Into<NodeId>) -> Self {
+        self.targets.insert(from.into(), to.into());
+        self
+    }
+
+    pub fn target(&self, from: &NodeId) -> Option<&NodeId> {
+        self.targets.get(from)
+    }
+}
+
+fn progress(status: NodeStatus) -> u8 {
+    match status {
+        NodeStatus::NotStarted => 0,
+        NodeStatus::Active => 1,
+        NodeStatus::Completed => 2,
+        NodeStatus::Failed | NodeStatus::Compensated | NodeStatus::Cancelled => 3,
+    }
+}
+
+/// Rebuilds `workflow` on the `target` definition. Each new node takes the
+/// most advanced status of the old nodes mapped onto it and every other node
+/// starts out `NotStarted`. The instance keeps its id, user, correlation
+/// key, status, variables, iteration counts, parent and stored version, so
+/// saving it replaces the old state.
+///
+/// Pending timers follow their node. Timers whose node moved are cancelled
+/// and scheduled again under the new id through the workflow's timer
+/// commands, and timers whose new node is not active are dropped. Pending
+/// retries move with their node and keep their attempt count, and so do the
+/// children sub-workflow nodes wait for.
+pub fn migrate_workflow(
+    workflow: Workflow,
+    target: WorkflowDefinition,
+    version: DefinitionRef,
+    mapping: &NodeMapping,
+) -> Result<Workflow, MigrationError> {
+    let mut migrated = target.instantiate(version)?;
+    for node in &mut migrated.nodes {
+        node.status = NodeStatus::NotStarted;
+        node.activated_at = None;
+    }
+    // Deadlines and sub-workflows of the new initial nodes do not apply to a
+    // running instance
+    migrated.scheduled_timers.clear();
+    migrated.take_timer_commands();
+    migrated.take_child_requests();
+
+    for node in &workflow.nodes {
+        let Some(target) = mapping.target(&node.id) else {
+            if node.status != NodeStatus::NotStarted {
+                return Err(MigrationError::UnmappedNode {
+                    node: node.id.clone(),
+                    status: node.status,
+                });
+            }
+            continue;
+        };
+        let Some(new_node) = migrated.node_mut(target) else {
+            return Err(MigrationError::UnknownTarget {
+                node: node.id.clone(),
+                target: target.clone(),
+            });
+        };
+        if progress(node.status) > progress(new_node.status) {
+            new_node.status = node.status;
+            new_node.activated_at = node.activated_at;
+            new_node.completed_at = node.completed_at;
+        }
+    }
+
+    // Cancels go out before schedules so a timer moved onto a node id that
+    // is itself being vacated is not cancelled along with the old ones.
+    let mut cancelled = HashSet::new();
+    let mut rescheduled = Vec::new();
+    for timer in workflow.scheduled_timers {
+        let target = mapping
+            .target(&timer.node_id)
+            .filter(|id| migrated.node(id).map(|n| n.status) == Some(NodeStatus::Active))
+            .cloned();
+
+        match target {
+            Some(node_id) if node_id == timer.node_id => migrated.scheduled_timers.push(timer),
+            target => {
+                if cancelled.insert(timer.node_id.clone()) {
+                    migrated.push_timer_command(TimerCommand::Cancel {
+                        node_id: timer.node_id.clone(),
+                    });
+                }
+                if let Some(node_id) = target {
+                    rescheduled.push(ScheduledTimer { node_id, ..timer });
+                }
+            }
+        }
+    }
+    for timer in rescheduled {
+        migrated.scheduled_timers.push(timer.clone());
+        migrated.push_timer_command(TimerCommand::Schedule(timer));
+    }
+
+    // Pending retries follow their node the same way
+    for (node_id, mut state) in workflow.retries {
+        let Some(target) = mapping
+            .target(&node_id)
+            .filter(|id| migrated.node(id).map(|n| n.status) == Some(NodeStatus::Active))
+        else {
+            continue;
+        };
+        if let RetryHook::Completed { targets } = &mut state.hook {
+            *targets = targets
+                .iter()
+                .filter_map(|id| mapping.target(id).cloned())
+                .collect();
+        }
+        migrated.retries.insert(target.clone(), state);
+    }
+
+    migrated.id = workflow.id;
+    migrated.user_id = workflow.user_id;
+    migrated.correlation_key = workflow.correlation_key;
+    migrated.status = workflow.status;
+    migrated.max_steps = workflow.max_steps;
+    migrated.variables = workflow.variables;
+    migrated.action_seq = workflow.action_seq;
+    migrated.pending_actions = workflow
+        .pending_actions
+        .into_iter()
+        .filter_map(|(id, action_id)| Some((mapping.target(&id)?.clone(), action_id)))
+        .collect();
+    migrated.completion_order = workflow
+        .completion_order
+        .iter()
+        .filter_map(|id| mapping.target(id).cloned())
+        .collect();
+    migrated.iterations = workflow
+        .iterations
+        .into_iter()
+        .filter_map(|(id, count)| Some((mapping.target(&id)?.clone(), count)))
+        .collect();
+    migrated.parent = workflow.parent;
+    migrated.children = workflow
+        .children
+        .into_iter()
+        .filter_map(|(id, child_id)| Some((mapping.target(&id)?.clone(), child_id)))
+        .collect();
+    migrated.version = workflow.version;
+
+    migrated.validate().map_err(MigrationError::Invalid)?;
+    Ok(migrated)
+}
+
+/// Migrates every active instance of `from` to `to` and returns how many
+/// were moved. Run it on a unit of work to migrate all instances or none;
+/// an instance saved concurrently fails the migration with a conflict.
+pub async fn migrate_instances<S>(
+    storage: &S,
+    from: &DefinitionRef,
+    to: &DefinitionRef,
+    mapping: &NodeMapping,
+) -> Result<usize, MigrationError>
+where
+    S: DefinitionRepository
+        + WorkflowRepository
+        + TimerRepository
+        + ActionRepository
+        + OutboxRepository
+        + TransitionRepository
+        + EventRepository
+        + Sync,
+{
+    let target = storage
+        .load_definition(to)
+        .await?
+        .ok_or_else(|| MigrationError::DefinitionNotFound(to.clone()))?;
+    // Definitions own their behaviors, so each instance gets a fresh copy.
+    let target = target.to_json()?;
+
+    let workflows = storage.get_active_workflows_for_definition(from).await?;
+    let count = workflows.len();
+    for workflow in workflows {
+        let definition = WorkflowDefinition::from_json(&target)?;
+        let mut migrated = migrate_workflow(workflow, definition, to.clone(), mapping)?;
+        save(storage, &mut migrated).await?;
+    }
+
+    Ok(count)
+}
@@ -1,5 +1,15 @@
+pub mod actions;
+pub mod definition;
+pub mod dispatcher;
+pub mod migration;
+pub mod outbox;
+pub mod replay;
 pub mod storage;
+pub mod timers;
 pub mod user_activity_workflow;
 
-use crate::models::{Event, Node};
+pub use actions::ActionRunner;
+pub use dispatcher::Dispatcher;
+pub use outbox::OutboxRelay;
 pub use storage::postgres::PostgresStorage;
+pub use timers::TimerScheduler;
@@ -0,0 +1,189 @@
+use crate::models::retry::RetryPolicy;
+use crate::workflow::storage::{error::StorageError, OutboxEntry, OutboxRepository};
+use crate::workflow::PostgresStorage;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use thiserror::Error;
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+
+#[derive(Error, Debug, Clone, PartialEq)]
+#[error("{0}")]
+pub struct OutboxError(pub String);
+
+impl OutboxError {
+    pub fn new(reason: impl Into<String>) -> Self {
+        Self(reason.into())
+    }
+}
+
+/// Delivers outbox messages of one topic to the outside world.
+///
+/// An entry is marked delivered once `handle` returns, so a crash in between
+/// delivers it again. Handlers that cannot tolerate this should use the entry
+/// id as an idempotency key.
+#[async_trait::async_trait]
+pub trait OutboxHandler: Send + Sync {
+    async fn handle(&self, entry: &OutboxEntry) -> Result<(), OutboxError>;
+}
+
+/// Background task that hands committed outbox entries to the handler
+/// registered for their topic.
+pub struct OutboxRelay {
+    storage: PostgresStorage,
+    handlers: HashMap<String, Arc<dyn OutboxHandler>>,
+    poll_interval: Duration,
+    lease: Duration,
+    batch_size: usize,
+    retry: RetryPolicy,
+}
+
+impl OutboxRelay {
+    pub fn new(storage: PostgresStorage) -> Self {
+        Self {
+            storage,
+            handlers: HashMap::new(),
+            poll_interval: Duration::from_secs(1),
+            lease: Duration::from_secs(60),
+            batch_size: 100,
+            retry: RetryPolicy::new(5, Duration::from_secs(1))
+                .with_max_backoff(Duration::from_secs(600)),
+        }
+    }
+
+    pub fn register(
+        mut self,
+        topic: impl Into<String>,
+        handler: impl OutboxHandler + 'static,
+    ) -> Self {
+        self.handlers.insert(topic.into(), Arc::new(handler));
+        self
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// How long a claimed entry is hidden from other relays. A handler that
+    /// takes more than half of it fails its delivery, so the entry is not
+    /// handed out again while it is being handled. Outcomes recorded after
+    /// the lease ran out are discarded.
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// How many entries one poll delivers at most.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// How many deliveries of an entry may fail before it is marked failed
+    /// and left alone.
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.retry.max_attempts = max_attempts;
+        self
+    }
+
+    /// How long to wait before delivering a failed entry again, and how
+    /// many deliveries may fail.
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.relay_pending().await {
+                eprintln!("Outbox relay error: {}", e);
+            }
+        }
+    }
+
+    /// Delivers up to a batch of due entries, oldest first, and returns how
+    /// many were handled. Each entry is claimed on its own just before it
+    /// is delivered, so concurrent relays never deliver the same entry and
+    /// no transaction stays open while handlers run. A failed entry waits
+    /// out its backoff before it is claimed again.
+    pub async fn relay_pending(&self) -> Result<usize, StorageError> {
+        let mut count = 0;
+        while count < self.batch_size {
+            let Some(entry) = self
+                .storage
+                .claim_outbox_entry(OffsetDateTime::now_utc(), self.lease)
+                .await?
+            else {
+                break;
+            };
+            count += 1;
+            if let Err(e) = self.relay(&entry).await {
+                eprintln!("Outbox entry {} could not be settled: {}", entry.id, e);
+            }
+        }
+
+        Ok(count)
+    }
+
+    /// Delivers a claimed entry and records the outcome.
+    async fn relay(&self, entry: &OutboxEntry) -> Result<(), StorageError> {
+        let settled = match self.deliver(entry).await {
+            Ok(()) => {
+                self.storage
+                    .mark_outbox_delivered(entry.id, entry.lease_token)
+                    .await?
+            }
+            Err(e) => {
+                let attempts = u32::try_from(entry.attempts).unwrap_or(0) + 1;
+                eprintln!(
+                    "Outbox entry {} ({}) failed on attempt {}: {}",
+                    entry.id, entry.topic, attempts, e
+                );
+                let retry_at = self.retry.allows_retry(attempts).then(|| {
+                    OffsetDateTime::now_utc() + self.retry.backoff(attempts, &entry.id.to_string())
+                });
+                self.storage
+                    .record_outbox_failure(entry.id, entry.lease_token, &e.to_string(), retry_at)
+                    .await?
+            }
+        };
+        if !settled {
+            eprintln!(
+                "Outbox entry {} was claimed by another relay before its outcome was recorded",
+                entry.id
+            );
+        }
+        Ok(())
+    }
+
+    async fn deliver(&self, entry: &OutboxEntry) -> Result<(), OutboxError> {
+        let Some(handler) = self.handlers.get(&entry.topic) else {
+            return Err(OutboxError::new(format!(
+                "no handler registered for topic {}",
+                entry.topic
+            )));
+        };
+        let timeout = self.delivery_timeout();
+        match tokio::time::timeout(timeout, handler.handle(entry)).await {
+            Ok(result) => result,
+            Err(_) => Err(OutboxError::new(format!(
+                "delivery took longer than {:?}",
+                timeout
+            ))),
+        }
+    }
+
+    /// Half the lease, leaving the other half to record the outcome while
+    /// the entry is still claimed.
+    fn delivery_timeout(&self) -> Duration {
+        self.lease / 2
+    }
+}
@@ -0,0 +1,92 @@
+use crate::models::{workflow::WorkflowError, CorrelationKey, Event, Workflow};
+use crate::workflow::definition::{DefinitionError, WorkflowDefinition};
+use crate::workflow::storage::{error::StorageError, EventRepository, StoredEvent};
+use thiserror::Error;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Definition error: {0}")]
+    Definition(#[from] DefinitionError),
+    #[error("Workflow error: {0}")]
+    Workflow(#[from] WorkflowError),
+}
+
+/// What to do with stored events whose type this build cannot decode.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnknownEvents {
+    Fail,
+    Skip,
+}
+
+/// Decodes stored events in order, dropping or rejecting unknown types.
+pub fn decode_events(
+    stored: &[StoredEvent],
+    unknown: UnknownEvents,
+) -> Result<Vec<Event>, StorageError> {
+    let mut events = Vec::with_capacity(stored.len());
+    for event in stored {
+        match event.decode() {
+            Ok(event) => events.push(event),
+            Err(StorageError::UnknownEventType(_)) if unknown == UnknownEvents::Skip => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(events)
+}
+
+/// Applies events to a workflow in order. Replaying the same events onto
+/// the same definition always yields the same node statuses.
+pub fn replay_events(workflow: &mut Workflow, events: &[Event]) -> Result<(), WorkflowError> {
+    for event in events {
+        workflow.process_event(event)?;
+    }
+    Ok(())
+}
+
+/// Rebuilds the state of a workflow for `user_id` by starting a fresh
+/// instance of `definition` and replaying every event recorded for the user
+/// that reached it. The instance takes `workflow_id`, if given, and
+/// `correlation_key`, so events recorded for another instance or correlated
+/// to another key are skipped, as they were when dispatched. `created_at`
+/// is when the original instance was created, which time gates on its
+/// initial nodes measure from.
+///
+/// Behaviors run again while replaying, and the timers and journal entries
+/// the replay produces are left on the workflow for the caller to persist or
+/// discard.
+pub async fn replay_workflow<S: EventRepository + Sync>(
+    storage: &S,
+    definition: WorkflowDefinition,
+    user_id: Uuid,
+    workflow_id: Option<Uuid>,
+    correlation_key: Option<&CorrelationKey>,
+    created_at: OffsetDateTime,
+    unknown: UnknownEvents,
+) -> Result<Workflow, ReplayError> {
+    let stored = storage.get_events_for_user(user_id).await?;
+
+    let mut workflow = definition.into_workflow_at(created_at)?;
+    if let Some(workflow_id) = workflow_id {
+        workflow.id = workflow_id;
+    }
+    workflow.user_id = user_id;
+    workflow.correlation_key = correlation_key.cloned();
+    // Events are applied at the time they were recorded so time-based gates
+    // decide the same way they did originally.
+    for event in &stored {
+        if !event.reaches(&workflow) {
+            continue;
+        }
+        match event.decode() {
+            Ok(decoded) => workflow.process_event_at(&decoded, event.created_at)?,
+            Err(StorageError::UnknownEventType(_)) if unknown == UnknownEvents::Skip => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(workflow)
+}
@@ -0,0 +1,58 @@
+use sqlx::pool::PoolConnection;
+use sqlx::{PgConnection, PgPool, Postgres, Transaction};
+use std::ops::{Deref, DerefMut};
+use tokio::sync::{Mutex, MutexGuard};
+
+/// Where a repository runs its queries: straight on the pool, or inside a
+/// transaction shared with other repositories.
+#[derive(Clone, Copy)]
+pub enum Connection<'a> {
+    Pool(&'a PgPool),
+    Transaction(&'a Mutex<Transaction<'static, Postgres>>),
+}
+
+impl<'a> Connection<'a> {
+    pub async fn acquire(&self) -> Result<ConnectionGuard<'a>, sqlx::Error> {
+        match *self {
+            Connection::Pool(pool) => Ok(ConnectionGuard::Pooled(Box::new(pool.acquire().await?))),
+            Connection::Transaction(tx) => Ok(ConnectionGuard::Transaction(tx.lock().await)),
+        }
+    }
+}
+
+impl<'a> From<&'a PgPool> for Connection<'a> {
+    fn from(pool: &'a PgPool) -> Self {
+        Connection::Pool(pool)
+    }
+}
+
+impl<'a> From<&'a Mutex<Transaction<'static, Postgres>>> for Connection<'a> {
+    fn from(tx: &'a Mutex<Transaction<'static, Postgres>>) -> Self {
+        Connection::Transaction(tx)
+    }
+}
+
+pub enum ConnectionGuard<'a> {
+    Pooled(Box<PoolConnection<Postgres>>),
+    Transaction(MutexGuard<'a, Transaction<'static, Postgres>>),
+}
+
+impl Deref for ConnectionGuard<'_> {
+    type Target = PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            ConnectionGuard::Pooled(conn) => conn,
+            ConnectionGuard::Transaction(tx) => tx,
+        }
+    }
+}
+
+impl DerefMut for ConnectionGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        match self {
+            ConnectionGuard::Pooled(conn) => conn,
+            ConnectionGuard::Transaction(tx) => tx,
+        }
+    }
+}
@@ -10,4 +10,15 @@ pub enum StorageError {
     Json(#[from] serde_json::Error),
     #[error("Bincode error: {0}")]
     Bincode(#[from] Box<bincode::ErrorKind>),
+    #[error("Workflow blob error: {0}")]
+    Blob(#[from] crate::models::workflow::BlobError),
+    #[error("Invalid data: {0}")]
+    InvalidData(String),
+    #[error("Unknown event type: {0}")]
+    UnknownEventType(String),
+    #[error("Workflow {workflow_id} was modified concurrently (expected version {expected})")]
+    Conflict {
+        workflow_id: uuid::Uuid,
+        expected: i64,
+    },
 }
@@ -1,9 +1,17 @@
+pub mod connection;
 pub mod error;
 pub mod postgres;
 pub mod repositories;
+pub mod unit_of_work;
 
-use crate::models::{Event, Workflow};
+use crate::models::{
+    action::ActionHook, node::NodeId, workflow::DefinitionRef, ActionRequest, CorrelationKey,
+    Event, OutboxMessage, TimerCommand, Transition, Workflow,
+};
+use crate::workflow::definition::WorkflowDefinition;
 use error::StorageError;
+use std::time::Duration;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
 #[async_trait::async_trait]
@@ -13,7 +21,7 @@ pub trait UserRepository {
 
 #[async_trait::async_trait]
 pub trait WorkflowRepository {
-    async fn save_workflow(&self, workflow: &Workflow) -> Result<(), StorageError>;
+    async fn save_workflow(&self, workflow: &mut Workflow) -> Result<(), StorageError>;
     async fn load_workflow(
         &self,
         user_id: Uuid,
@@ -23,17 +31,215 @@ pub trait WorkflowRepository {
         &self,
         user_id: Uuid,
     ) -> Result<Vec<Workflow>, StorageError>;
+    /// Active workflows of the user started with the given correlation key.
+    async fn get_active_workflows_for_correlation(
+        &self,
+        user_id: Uuid,
+        correlation_key: &CorrelationKey,
+    ) -> Result<Vec<Workflow>, StorageError>;
     async fn get_all_workflows(&self) -> Result<Vec<(Uuid, Uuid, String, String)>, StorageError>;
+    /// Active workflows still running on the given definition version.
+    async fn get_active_workflows_for_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Vec<Workflow>, StorageError>;
+}
+
+#[async_trait::async_trait]
+pub trait DefinitionRepository {
+    /// Stores the definition as the next version under its name.
+    async fn save_definition(
+        &self,
+        definition: &WorkflowDefinition,
+    ) -> Result<DefinitionRef, StorageError>;
+    async fn load_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Option<WorkflowDefinition>, StorageError>;
+    async fn latest_definition(
+        &self,
+        name: &str,
+    ) -> Result<Option<(DefinitionRef, WorkflowDefinition)>, StorageError>;
 }
 
 #[async_trait::async_trait]
 pub trait EventRepository {
-    async fn save_event(&self, user_id: Uuid, event: &Event) -> Result<(), StorageError>;
+    /// Records an event of the user. Events delivered to one workflow, such
+    /// as its timers and action results, pass its id as `workflow_id`.
+    async fn save_event(
+        &self,
+        user_id: Uuid,
+        workflow_id: Option<Uuid>,
+        correlation_key: Option<&CorrelationKey>,
+        event: &Event,
+    ) -> Result<(), StorageError>;
     async fn get_all_events(
         &self,
     ) -> Result<Vec<(Uuid, Uuid, String, serde_json::Value, time::OffsetDateTime)>, StorageError>;
+    /// Events of one user in the order they were recorded.
+    async fn get_events_for_user(&self, user_id: Uuid) -> Result<Vec<StoredEvent>, StorageError>;
+}
+
+/// Row of the events table, still in its stored encoding.
+#[derive(Debug, Clone)]
+pub struct StoredEvent {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub event_type: String,
+    pub event_data: serde_json::Value,
+    pub workflow_id: Option<Uuid>,
+    pub correlation_key: Option<CorrelationKey>,
+    pub created_at: OffsetDateTime,
+}
+
+impl StoredEvent {
+    pub fn decode(&self) -> Result<Event, StorageError> {
+        repositories::events::decode_event(&self.event_type, &self.event_data)
+    }
+
+    /// Whether the event was delivered to `workflow`. Events recorded for
+    /// one workflow only reach that one, events with a correlation key only
+    /// the workflows started with it, and other events every workflow of
+    /// their user.
+    pub fn reaches(&self, workflow: &Workflow) -> bool {
+        self.workflow_id.is_none_or(|id| id == workflow.id)
+            && (self.correlation_key.is_none() || self.correlation_key == workflow.correlation_key)
+    }
+}
+
+#[async_trait::async_trait]
+pub trait TransitionRepository {
+    async fn save_transitions(
+        &self,
+        workflow_id: Uuid,
+        transitions: &[Transition],
+    ) -> Result<(), StorageError>;
+    async fn get_transitions(&self, workflow_id: Uuid) -> Result<Vec<Transition>, StorageError>;
+}
+
+/// Timer row claimed by the scheduler because its fire time has passed.
+#[derive(Debug, Clone)]
+pub struct DueTimer {
+    pub id: Uuid,
+    pub workflow_id: Uuid,
+    pub user_id: Uuid,
+    pub timer_id: String,
+}
+
+#[async_trait::async_trait]
+pub trait TimerRepository {
+    async fn apply_timer_commands(
+        &self,
+        workflow: &Workflow,
+        commands: &[TimerCommand],
+    ) -> Result<(), StorageError>;
+    async fn claim_due_timers(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<DueTimer>, StorageError>;
+    async fn mark_timer_fired(&self, timer_id: Uuid) -> Result<(), StorageError>;
+}
+
+/// Action row claimed by the action runner.
+#[derive(Debug, Clone)]
+pub struct PendingAction {
+    pub id: Uuid,
+    pub workflow_id: Uuid,
+    pub user_id: Uuid,
+    pub node_id: NodeId,
+    pub action_id: String,
+    pub hook: ActionHook,
+    /// Identifies this claim. Only the runner holding it may record the
+    /// action's outcome.
+    pub lease_token: Uuid,
+}
+
+#[async_trait::async_trait]
+pub trait ActionRepository {
+    async fn enqueue_actions(
+        &self,
+        workflow: &Workflow,
+        requests: &[ActionRequest],
+    ) -> Result<(), StorageError>;
+    /// Claims the oldest pending action not claimed by another runner and
+    /// hides it from other runners until the lease has passed.
+    async fn claim_pending_action(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<PendingAction>, StorageError>;
+    /// Marks a claimed action done. Returns `false`, and changes nothing, if
+    /// the claim was lost to another runner.
+    async fn mark_action_done(
+        &self,
+        action_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError>;
+}
+
+/// Outbox row claimed by the relay for delivery.
+#[derive(Debug, Clone)]
+pub struct OutboxEntry {
+    pub id: Uuid,
+    pub workflow_id: Uuid,
+    pub user_id: Uuid,
+    pub node_id: NodeId,
+    pub topic: String,
+    pub payload: serde_json::Value,
+    /// Delivery attempts made before this one.
+    pub attempts: i32,
+    /// Identifies this claim. Only the relay holding it may record the
+    /// outcome of the delivery.
+    pub lease_token: Uuid,
+}
+
+#[async_trait::async_trait]
+pub trait OutboxRepository {
+    async fn enqueue_outbox(
+        &self,
+        workflow: &Workflow,
+        messages: &[OutboxMessage],
+    ) -> Result<(), StorageError>;
+    /// Claims the oldest pending entry that is due at `now` and hides it
+    /// from other relays until the lease has passed.
+    async fn claim_outbox_entry(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<OutboxEntry>, StorageError>;
+    /// Marks a claimed entry delivered. Returns `false`, and changes
+    /// nothing, if the claim was lost to another relay.
+    async fn mark_outbox_delivered(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError>;
+    /// Stops delivery of the workflow's pending entries.
+    async fn cancel_outbox_entries(&self, workflow_id: Uuid) -> Result<(), StorageError>;
+    /// Records a failed delivery of a claimed entry. The entry is tried
+    /// again at `retry_at`, or marked failed if there is none. Returns
+    /// `false`, and changes nothing, if the claim was lost to another relay.
+    async fn record_outbox_failure(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+        error: &str,
+        retry_at: Option<OffsetDateTime>,
+    ) -> Result<bool, StorageError>;
 }
 
-pub trait Storage: UserRepository + WorkflowRepository + EventRepository {
+#[async_trait::async_trait]
+pub trait Storage:
+    UserRepository
+    + WorkflowRepository
+    + DefinitionRepository
+    + EventRepository
+    + TimerRepository
+    + ActionRepository
+    + OutboxRepository
+    + TransitionRepository
+{
     async fn setup_database(&self) -> Result<(), StorageError>;
 }
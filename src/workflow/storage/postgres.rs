@@ -1,12 +1,27 @@
-use crate::models::{Event, Workflow};
+use crate::models::{
+    workflow::DefinitionRef, ActionRequest, CorrelationKey, Event, OutboxMessage, TimerCommand,
+    Transition, Workflow,
+};
+use crate::workflow::definition::WorkflowDefinition;
+use crate::workflow::dispatcher::Dispatcher;
 use crate::workflow::storage::error::StorageError;
 use crate::workflow::storage::repositories::{
-    PostgresEventRepository, PostgresUserRepository, PostgresWorkflowRepository,
+    PostgresActionRepository, PostgresDefinitionRepository, PostgresEventRepository,
+    PostgresOutboxRepository, PostgresTimerRepository, PostgresTransitionRepository,
+    PostgresUserRepository, PostgresWorkflowRepository,
+};
+use crate::workflow::storage::unit_of_work::PostgresUnitOfWork;
+use crate::workflow::storage::{
+    ActionRepository, DefinitionRepository, DueTimer, EventRepository, OutboxEntry,
+    OutboxRepository, PendingAction, Storage, StoredEvent, TimerRepository, TransitionRepository,
+    UserRepository, WorkflowRepository,
 };
-use crate::workflow::storage::{EventRepository, Storage, UserRepository, WorkflowRepository};
 use sqlx::PgPool;
+use std::time::Duration;
+use time::OffsetDateTime;
 use uuid::Uuid;
 
+#[derive(Clone)]
 pub struct PostgresStorage {
     pool: PgPool,
 }
@@ -16,6 +31,36 @@ impl PostgresStorage {
         let pool = PgPool::connect(database_url).await?;
         Ok(Self { pool })
     }
+
+    /// Starts a transaction that repositories can share through the returned
+    /// unit of work.
+    pub async fn begin(&self) -> Result<PostgresUnitOfWork, StorageError> {
+        Ok(PostgresUnitOfWork::new(self.pool.begin().await?))
+    }
+
+    /// Cancels one workflow, see `Dispatcher::cancel_workflow`.
+    pub async fn cancel_workflow(
+        &self,
+        user_id: Uuid,
+        workflow_id: Uuid,
+        reason: &str,
+    ) -> Result<bool, StorageError> {
+        Dispatcher::new(self.clone())
+            .cancel_workflow(user_id, workflow_id, reason)
+            .await
+    }
+
+    /// Cancels every active workflow of a user, see
+    /// `Dispatcher::cancel_user_workflows`.
+    pub async fn cancel_user_workflows(
+        &self,
+        user_id: Uuid,
+        reason: &str,
+    ) -> Result<usize, StorageError> {
+        Dispatcher::new(self.clone())
+            .cancel_user_workflows(user_id, reason)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
@@ -29,7 +74,7 @@ impl UserRepository for PostgresStorage {
 
 #[async_trait::async_trait]
 impl WorkflowRepository for PostgresStorage {
-    async fn save_workflow(&self, workflow: &Workflow) -> Result<(), StorageError> {
+    async fn save_workflow(&self, workflow: &mut Workflow) -> Result<(), StorageError> {
         PostgresWorkflowRepository::new(&self.pool)
             .save_workflow(workflow)
             .await
@@ -54,18 +99,73 @@ impl WorkflowRepository for PostgresStorage {
             .await
     }
 
+    async fn get_active_workflows_for_correlation(
+        &self,
+        user_id: Uuid,
+        correlation_key: &CorrelationKey,
+    ) -> Result<Vec<Workflow>, StorageError> {
+        PostgresWorkflowRepository::new(&self.pool)
+            .get_active_workflows_for_correlation(user_id, correlation_key)
+            .await
+    }
+
     async fn get_all_workflows(&self) -> Result<Vec<(Uuid, Uuid, String, String)>, StorageError> {
         PostgresWorkflowRepository::new(&self.pool)
             .get_all_workflows()
             .await
     }
+
+    async fn get_active_workflows_for_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Vec<Workflow>, StorageError> {
+        PostgresWorkflowRepository::new(&self.pool)
+            .get_active_workflows_for_definition(definition)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl DefinitionRepository for PostgresStorage {
+    async fn save_definition(
+        &self,
+        definition: &WorkflowDefinition,
+    ) -> Result<DefinitionRef, StorageError> {
+        PostgresDefinitionRepository::new(&self.pool)
+            .save_definition(definition)
+            .await
+    }
+
+    async fn load_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Option<WorkflowDefinition>, StorageError> {
+        PostgresDefinitionRepository::new(&self.pool)
+            .load_definition(definition)
+            .await
+    }
+
+    async fn latest_definition(
+        &self,
+        name: &str,
+    ) -> Result<Option<(DefinitionRef, WorkflowDefinition)>, StorageError> {
+        PostgresDefinitionRepository::new(&self.pool)
+            .latest_definition(name)
+            .await
+    }
 }
 
 #[async_trait::async_trait]
 impl EventRepository for PostgresStorage {
-    async fn save_event(&self, user_id: Uuid, event: &Event) -> Result<(), StorageError> {
+    async fn save_event(
+        &self,
+        user_id: Uuid,
+        workflow_id: Option<Uuid>,
+        correlation_key: Option<&CorrelationKey>,
+        event: &Event,
+    ) -> Result<(), StorageError> {
         PostgresEventRepository::new(&self.pool)
-            .save_event(user_id, event)
+            .save_event(user_id, workflow_id, correlation_key, event)
             .await
     }
 
@@ -77,8 +177,148 @@ impl EventRepository for PostgresStorage {
             .get_all_events()
             .await
     }
+
+    async fn get_events_for_user(&self, user_id: Uuid) -> Result<Vec<StoredEvent>, StorageError> {
+        PostgresEventRepository::new(&self.pool)
+            .get_events_for_user(user_id)
+            .await
+    }
 }
 
+#[async_trait::async_trait]
+impl ActionRepository for PostgresStorage {
+    async fn enqueue_actions(
+        &self,
+        workflow: &Workflow,
+        requests: &[ActionRequest],
+    ) -> Result<(), StorageError> {
+        PostgresActionRepository::new(&self.pool)
+            .enqueue_actions(workflow, requests)
+            .await
+    }
+
+    async fn claim_pending_action(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<PendingAction>, StorageError> {
+        PostgresActionRepository::new(&self.pool)
+            .claim_pending_action(now, lease)
+            .await
+    }
+
+    async fn mark_action_done(
+        &self,
+        action_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError> {
+        PostgresActionRepository::new(&self.pool)
+            .mark_action_done(action_id, lease_token)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl OutboxRepository for PostgresStorage {
+    async fn enqueue_outbox(
+        &self,
+        workflow: &Workflow,
+        messages: &[OutboxMessage],
+    ) -> Result<(), StorageError> {
+        PostgresOutboxRepository::new(&self.pool)
+            .enqueue_outbox(workflow, messages)
+            .await
+    }
+
+    async fn claim_outbox_entry(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<OutboxEntry>, StorageError> {
+        PostgresOutboxRepository::new(&self.pool)
+            .claim_outbox_entry(now, lease)
+            .await
+    }
+
+    async fn mark_outbox_delivered(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError> {
+        PostgresOutboxRepository::new(&self.pool)
+            .mark_outbox_delivered(entry_id, lease_token)
+            .await
+    }
+
+    async fn cancel_outbox_entries(&self, workflow_id: Uuid) -> Result<(), StorageError> {
+        PostgresOutboxRepository::new(&self.pool)
+            .cancel_outbox_entries(workflow_id)
+            .await
+    }
+
+    async fn record_outbox_failure(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+        error: &str,
+        retry_at: Option<OffsetDateTime>,
+    ) -> Result<bool, StorageError> {
+        PostgresOutboxRepository::new(&self.pool)
+            .record_outbox_failure(entry_id, lease_token, error, retry_at)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl TimerRepository for PostgresStorage {
+    async fn apply_timer_commands(
+        &self,
+        workflow: &Workflow,
+        commands: &[TimerCommand],
+    ) -> Result<(), StorageError> {
+        PostgresTimerRepository::new(&self.pool)
+            .apply_timer_commands(workflow, commands)
+            .await
+    }
+
+    async fn claim_due_timers(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<DueTimer>, StorageError> {
+        PostgresTimerRepository::new(&self.pool)
+            .claim_due_timers(now, lease, limit)
+            .await
+    }
+
+    async fn mark_timer_fired(&self, timer_id: Uuid) -> Result<(), StorageError> {
+        PostgresTimerRepository::new(&self.pool)
+            .mark_timer_fired(timer_id)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl TransitionRepository for PostgresStorage {
+    async fn save_transitions(
+        &self,
+        workflow_id: Uuid,
+        transitions: &[Transition],
+    ) -> Result<(), StorageError> {
+        PostgresTransitionRepository::new(&self.pool)
+            .save_transitions(workflow_id, transitions)
+            .await
+    }
+
+    async fn get_transitions(&self, workflow_id: Uuid) -> Result<Vec<Transition>, StorageError> {
+        PostgresTransitionRepository::new(&self.pool)
+            .get_transitions(workflow_id)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
 impl Storage for PostgresStorage {
     async fn setup_database(&self) -> Result<(), StorageError> {
         Ok(())
@@ -0,0 +1,104 @@
+use crate::models::{ActionRequest, Workflow};
+use crate::workflow::storage::connection::Connection;
+use crate::workflow::storage::error::StorageError;
+use crate::workflow::storage::{ActionRepository, PendingAction};
+use sqlx::Row;
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+pub struct PostgresActionRepository<'a> {
+    conn: Connection<'a>,
+}
+
+impl<'a> PostgresActionRepository<'a> {
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> ActionRepository for PostgresActionRepository<'a> {
+    async fn enqueue_actions(
+        &self,
+        workflow: &Workflow,
+        requests: &[ActionRequest],
+    ) -> Result<(), StorageError> {
+        for request in requests {
+            sqlx::query(
+                "INSERT INTO node_actions (workflow_id, user_id, node_id, action_id, hook, status)
+                 VALUES ($1, $2, $3, $4, $5, 'pending')
+                 ON CONFLICT (workflow_id, action_id) DO NOTHING",
+            )
+            .bind(workflow.id)
+            .bind(workflow.user_id)
+            .bind(request.node_id.as_str())
+            .bind(&request.action_id)
+            .bind(request.hook.to_string())
+            .execute(&mut *self.conn.acquire().await?)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn claim_pending_action(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<PendingAction>, StorageError> {
+        let row = sqlx::query(
+            "UPDATE node_actions
+             SET locked_until = $2,
+                 lease_token = $3,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = (
+                 SELECT id FROM node_actions
+                 WHERE status = 'pending'
+                   AND (locked_until IS NULL OR locked_until <= $1)
+                 ORDER BY created_at
+                 LIMIT 1
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING id, workflow_id, user_id, node_id, action_id, hook, lease_token",
+        )
+        .bind(now)
+        .bind(now + lease)
+        .bind(Uuid::new_v4())
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await?;
+
+        row.map(|row| {
+            let hook: String = row.try_get("hook")?;
+            Ok(PendingAction {
+                id: row.try_get("id")?,
+                workflow_id: row.try_get("workflow_id")?,
+                user_id: row.try_get("user_id")?,
+                node_id: row.try_get::<String, _>("node_id")?.into(),
+                action_id: row.try_get("action_id")?,
+                hook: hook.parse().map_err(StorageError::InvalidData)?,
+                lease_token: row.try_get("lease_token")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn mark_action_done(
+        &self,
+        action_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError> {
+        let result = sqlx::query(
+            "UPDATE node_actions
+             SET status = 'done',
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = $1 AND status = 'pending' AND lease_token = $2",
+        )
+        .bind(action_id)
+        .bind(lease_token)
+        .execute(&mut *self.conn.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,94 @@
+use crate::models::workflow::DefinitionRef;
+use crate::workflow::definition::WorkflowDefinition;
+use crate::workflow::storage::connection::Connection;
+use crate::workflow::storage::error::StorageError;
+use crate::workflow::storage::DefinitionRepository;
+use sqlx::Row;
+
+pub struct PostgresDefinitionRepository<'a> {
+    conn: Connection<'a>,
+}
+
+impl<'a> PostgresDefinitionRepository<'a> {
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> DefinitionRepository for PostgresDefinitionRepository<'a> {
+    async fn save_definition(
+        &self,
+        definition: &WorkflowDefinition,
+    ) -> Result<DefinitionRef, StorageError> {
+        let json = serde_json::to_string(definition)?;
+
+        let row = sqlx::query(
+            "INSERT INTO workflow_definitions (name, version, definition)
+             SELECT $1, COALESCE(MAX(version), 0) + 1, $2::jsonb
+             FROM workflow_definitions
+             WHERE name = $1
+             RETURNING version",
+        )
+        .bind(&definition.name)
+        .bind(json)
+        .fetch_one(&mut *self.conn.acquire().await?)
+        .await?;
+
+        Ok(DefinitionRef {
+            name: definition.name.clone(),
+            version: row.try_get("version")?,
+        })
+    }
+
+    async fn load_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Option<WorkflowDefinition>, StorageError> {
+        let row = sqlx::query(
+            "SELECT definition::text AS definition
+             FROM workflow_definitions
+             WHERE name = $1 AND version = $2",
+        )
+        .bind(&definition.name)
+        .bind(definition.version)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await?;
+
+        match row {
+            Some(row) => {
+                let json: String = row.try_get("definition")?;
+                Ok(Some(serde_json::from_str(&json)?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn latest_definition(
+        &self,
+        name: &str,
+    ) -> Result<Option<(DefinitionRef, WorkflowDefinition)>, StorageError> {
+        let row = sqlx::query(
+            "SELECT version, definition::text AS definition
+             FROM workflow_definitions
+             WHERE name = $1
+             ORDER BY version DESC
+             LIMIT 1",
+        )
+        .bind(name)
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await?;
+
+        match row {
+            Some(row) => {
+                let json: String = row.try_get("definition")?;
+                let definition_ref = DefinitionRef {
+                    name: name.to_string(),
+                    version: row.try_get("version")?,
+                };
+                Ok(Some((definition_ref, serde_json::from_str(&json)?)))
+            }
+            None => Ok(None),
+        }
+    }
+}
@@ -1,37 +1,130 @@
-use crate::models::Event;
-use crate::workflow::storage::{EventRepository, StorageError};
+use crate::models::{CorrelationKey, Event};
+use crate::workflow::storage::connection::Connection;
+use crate::workflow::storage::{EventRepository, StorageError, StoredEvent};
 use serde_json::Value as JsonValue;
-use sqlx::{Executor, PgPool, Row};
+use sqlx::{Executor, Row};
 use uuid::Uuid;
 
+pub use crate::models::event::{encode_event, CUSTOM_PREFIX};
+
+/// Inverse of `encode_event`. Event types this build does not know about are
+/// reported as `StorageError::UnknownEventType` so callers can decide whether
+/// to skip them.
+pub fn decode_event(event_type: &str, event_data: &JsonValue) -> Result<Event, StorageError> {
+    match event_type {
+        "user_activity" => Ok(Event::UserActivity),
+        "timer" => {
+            let timer_id = event_data
+                .get("timer_id")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| {
+                    StorageError::InvalidData(format!(
+                        "timer event without timer_id: {}",
+                        event_data
+                    ))
+                })?;
+            Ok(Event::Timer {
+                timer_id: timer_id.to_string(),
+            })
+        }
+        "action" => {
+            let field = |name: &str| {
+                event_data.get(name).cloned().ok_or_else(|| {
+                    StorageError::InvalidData(format!(
+                        "action event without {}: {}",
+                        name, event_data
+                    ))
+                })
+            };
+            Ok(Event::Action {
+                node_id: serde_json::from_value(field("node_id")?)?,
+                action_id: serde_json::from_value(field("action_id")?)?,
+                result: serde_json::from_value(field("result")?)?,
+            })
+        }
+        "cancelled" => {
+            let reason = event_data
+                .get("reason")
+                .and_then(JsonValue::as_str)
+                .ok_or_else(|| {
+                    StorageError::InvalidData(format!(
+                        "cancelled event without reason: {}",
+                        event_data
+                    ))
+                })?;
+            Ok(Event::Cancelled {
+                reason: reason.to_string(),
+            })
+        }
+        "child_finished" => {
+            let field = |name: &str| {
+                event_data.get(name).cloned().ok_or_else(|| {
+                    StorageError::InvalidData(format!(
+                        "child_finished event without {}: {}",
+                        name, event_data
+                    ))
+                })
+            };
+            let status = field("status")?;
+            let status = status
+                .as_str()
+                .and_then(|s| s.parse().ok())
+                .ok_or_else(|| {
+                    StorageError::InvalidData(format!("invalid child workflow status: {}", status))
+                })?;
+            Ok(Event::ChildFinished {
+                node_id: serde_json::from_value(field("node_id")?)?,
+                child_id: serde_json::from_value(field("child_id")?)?,
+                status,
+                reason: serde_json::from_value(
+                    event_data.get("reason").cloned().unwrap_or_default(),
+                )?,
+            })
+        }
+        _ => match event_type.strip_prefix(CUSTOM_PREFIX) {
+            Some(custom) => Ok(Event::Custom {
+                event_type: custom.to_string(),
+                payload: event_data.clone(),
+            }),
+            None => Err(StorageError::UnknownEventType(event_type.to_string())),
+        },
+    }
+}
+
 pub struct PostgresEventRepository<'a> {
-    pool: &'a PgPool,
+    conn: Connection<'a>,
 }
 
 impl<'a> PostgresEventRepository<'a> {
-    pub fn new(pool: &'a PgPool) -> Self {
-        Self { pool }
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
     }
 }
 
 #[async_trait::async_trait]
 impl<'a> EventRepository for PostgresEventRepository<'a> {
-    async fn save_event(&self, user_id: Uuid, event: &Event) -> Result<(), StorageError> {
-        let (event_type, event_data) = match event {
-            Event::UserActivity => ("user_activity".to_string(), serde_json::Value::Null),
-            Event::Timer { timer_id } => (
-                "timer".to_string(),
-                serde_json::json!({ "timer_id": timer_id }),
-            ),
-        };
-
-        let query = "INSERT INTO events (user_id, event_type, event_data) VALUES ($1, $2, $3)";
-        self.pool
+    async fn save_event(
+        &self,
+        user_id: Uuid,
+        workflow_id: Option<Uuid>,
+        correlation_key: Option<&CorrelationKey>,
+        event: &Event,
+    ) -> Result<(), StorageError> {
+        let (event_type, event_data) = encode_event(event);
+
+        let query = "INSERT INTO events
+                         (user_id, event_type, event_data, workflow_id, correlation_key)
+                     VALUES ($1, $2, $3, $4, $5)";
+        self.conn
+            .acquire()
+            .await?
             .execute(
                 sqlx::query(query)
                     .bind(user_id)
                     .bind(&event_type)
-                    .bind(&event_data),
+                    .bind(&event_data)
+                    .bind(workflow_id)
+                    .bind(correlation_key.map(CorrelationKey::as_str)),
             )
             .await?;
         Ok(())
@@ -40,8 +133,11 @@ impl<'a> EventRepository for PostgresEventRepository<'a> {
     async fn get_all_events(
         &self,
     ) -> Result<Vec<(Uuid, Uuid, String, JsonValue, time::OffsetDateTime)>, StorageError> {
-        let query = "SELECT id, user_id, event_type, event_data, created_at FROM events ORDER BY created_at";
-        let rows = sqlx::query(query).fetch_all(self.pool).await?;
+        let query =
+            "SELECT id, user_id, event_type, event_data, created_at FROM events ORDER BY seq";
+        let rows = sqlx::query(query)
+            .fetch_all(&mut *self.conn.acquire().await?)
+            .await?;
 
         let mut events = Vec::new();
         for row in rows {
@@ -56,4 +152,33 @@ impl<'a> EventRepository for PostgresEventRepository<'a> {
 
         Ok(events)
     }
+
+    async fn get_events_for_user(&self, user_id: Uuid) -> Result<Vec<StoredEvent>, StorageError> {
+        let rows = sqlx::query(
+            "SELECT id, event_type, event_data, workflow_id, correlation_key, created_at
+             FROM events
+             WHERE user_id = $1
+             ORDER BY seq",
+        )
+        .bind(user_id)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await?;
+
+        let mut events = Vec::new();
+        for row in rows {
+            events.push(StoredEvent {
+                id: row.try_get("id")?,
+                user_id,
+                event_type: row.try_get("event_type")?,
+                event_data: row.try_get("event_data")?,
+                workflow_id: row.try_get("workflow_id")?,
+                correlation_key: row
+                    .try_get::<Option<String>, _>("correlation_key")?
+                    .map(CorrelationKey::from),
+                created_at: row.try_get("created_at")?,
+            });
+        }
+
+        Ok(events)
+    }
 }
@@ -1,7 +1,17 @@
+pub mod actions;
+pub mod definitions;
 pub mod events;
+pub mod outbox;
+pub mod timers;
+pub mod transitions;
 pub mod users;
 pub mod workflows;
 
+pub use actions::PostgresActionRepository;
+pub use definitions::PostgresDefinitionRepository;
 pub use events::PostgresEventRepository;
+pub use outbox::PostgresOutboxRepository;
+pub use timers::PostgresTimerRepository;
+pub use transitions::PostgresTransitionRepository;
 pub use users::PostgresUserRepository;
 pub use workflows::PostgresWorkflowRepository;
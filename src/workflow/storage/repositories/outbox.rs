@@ -0,0 +1,145 @@
+use crate::models::{OutboxMessage, Workflow};
+use crate::workflow::storage::connection::Connection;
+use crate::workflow::storage::error::StorageError;
+use crate::workflow::storage::{OutboxEntry, OutboxRepository};
+use sqlx::Row;
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+pub struct PostgresOutboxRepository<'a> {
+    conn: Connection<'a>,
+}
+
+impl<'a> PostgresOutboxRepository<'a> {
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> OutboxRepository for PostgresOutboxRepository<'a> {
+    async fn enqueue_outbox(
+        &self,
+        workflow: &Workflow,
+        messages: &[OutboxMessage],
+    ) -> Result<(), StorageError> {
+        for message in messages {
+            sqlx::query(
+                "INSERT INTO outbox (workflow_id, user_id, node_id, topic, payload, status)
+                 VALUES ($1, $2, $3, $4, $5, 'pending')",
+            )
+            .bind(workflow.id)
+            .bind(workflow.user_id)
+            .bind(message.node_id.as_str())
+            .bind(&message.topic)
+            .bind(&message.payload)
+            .execute(&mut *self.conn.acquire().await?)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn claim_outbox_entry(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<OutboxEntry>, StorageError> {
+        // Pushing next_attempt_at past the lease hides the entry from other
+        // relays without holding its row locked during delivery.
+        let row = sqlx::query(
+            "UPDATE outbox
+             SET next_attempt_at = $2,
+                 lease_token = $3,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = (
+                 SELECT id FROM outbox
+                 WHERE status = 'pending' AND next_attempt_at <= $1
+                 ORDER BY created_at
+                 LIMIT 1
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING id, workflow_id, user_id, node_id, topic, payload, attempts, lease_token",
+        )
+        .bind(now)
+        .bind(now + lease)
+        .bind(Uuid::new_v4())
+        .fetch_optional(&mut *self.conn.acquire().await?)
+        .await?;
+
+        row.map(|row| {
+            Ok(OutboxEntry {
+                id: row.try_get("id")?,
+                workflow_id: row.try_get("workflow_id")?,
+                user_id: row.try_get("user_id")?,
+                node_id: row.try_get::<String, _>("node_id")?.into(),
+                topic: row.try_get("topic")?,
+                payload: row.try_get("payload")?,
+                attempts: row.try_get("attempts")?,
+                lease_token: row.try_get("lease_token")?,
+            })
+        })
+        .transpose()
+    }
+
+    async fn mark_outbox_delivered(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError> {
+        let result = sqlx::query(
+            "UPDATE outbox
+             SET status = 'delivered',
+                 attempts = attempts + 1,
+                 delivered_at = CURRENT_TIMESTAMP,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = $1 AND status = 'pending' AND lease_token = $2",
+        )
+        .bind(entry_id)
+        .bind(lease_token)
+        .execute(&mut *self.conn.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn cancel_outbox_entries(&self, workflow_id: Uuid) -> Result<(), StorageError> {
+        sqlx::query(
+            "UPDATE outbox
+             SET status = 'cancelled', updated_at = CURRENT_TIMESTAMP
+             WHERE workflow_id = $1 AND status = 'pending'",
+        )
+        .bind(workflow_id)
+        .execute(&mut *self.conn.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn record_outbox_failure(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+        error: &str,
+        retry_at: Option<OffsetDateTime>,
+    ) -> Result<bool, StorageError> {
+        let result = sqlx::query(
+            "UPDATE outbox
+             SET status = CASE WHEN $3::TIMESTAMPTZ IS NULL THEN 'failed' ELSE 'pending' END,
+                 next_attempt_at = COALESCE($3, next_attempt_at),
+                 attempts = attempts + 1,
+                 last_error = $2,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = $1 AND status = 'pending' AND lease_token = $4",
+        )
+        .bind(entry_id)
+        .bind(error)
+        .bind(retry_at)
+        .bind(lease_token)
+        .execute(&mut *self.conn.acquire().await?)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+}
@@ -0,0 +1,115 @@
+use crate::models::{TimerCommand, Workflow};
+use crate::workflow::storage::connection::Connection;
+use crate::workflow::storage::error::StorageError;
+use crate::workflow::storage::{DueTimer, TimerRepository};
+use sqlx::Row;
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+pub struct PostgresTimerRepository<'a> {
+    conn: Connection<'a>,
+}
+
+impl<'a> PostgresTimerRepository<'a> {
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> TimerRepository for PostgresTimerRepository<'a> {
+    async fn apply_timer_commands(
+        &self,
+        workflow: &Workflow,
+        commands: &[TimerCommand],
+    ) -> Result<(), StorageError> {
+        for command in commands {
+            match command {
+                TimerCommand::Schedule(timer) => {
+                    sqlx::query(
+                        "INSERT INTO timers (workflow_id, user_id, node_id, timer_id, fire_at, status)
+                         VALUES ($1, $2, $3, $4, $5, 'pending')",
+                    )
+                    .bind(workflow.id)
+                    .bind(workflow.user_id)
+                    .bind(timer.node_id.to_string())
+                    .bind(&timer.timer_id)
+                    .bind(timer.fire_at)
+                    .execute(&mut *self.conn.acquire().await?)
+                    .await?;
+                }
+                TimerCommand::Cancel { node_id } => {
+                    sqlx::query(
+                        "UPDATE timers
+                         SET status = 'cancelled',
+                             updated_at = CURRENT_TIMESTAMP
+                         WHERE workflow_id = $1 AND node_id = $2 AND status = 'pending'",
+                    )
+                    .bind(workflow.id)
+                    .bind(node_id.to_string())
+                    .execute(&mut *self.conn.acquire().await?)
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn claim_due_timers(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<DueTimer>, StorageError> {
+        // Claimed timers stay pending until they are marked fired, so a
+        // scheduler that dies mid-delivery only delays them by the lease.
+        let rows = sqlx::query(
+            "UPDATE timers
+             SET locked_until = $2,
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id IN (
+                 SELECT id FROM timers
+                 WHERE status = 'pending'
+                   AND fire_at <= $1
+                   AND (locked_until IS NULL OR locked_until <= $1)
+                 ORDER BY fire_at
+                 LIMIT $3
+                 FOR UPDATE SKIP LOCKED
+             )
+             RETURNING id, workflow_id, user_id, timer_id",
+        )
+        .bind(now)
+        .bind(now + lease)
+        .bind(limit)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await?;
+
+        let mut timers = Vec::new();
+        for row in rows {
+            timers.push(DueTimer {
+                id: row.try_get("id")?,
+                workflow_id: row.try_get("workflow_id")?,
+                user_id: row.try_get("user_id")?,
+                timer_id: row.try_get("timer_id")?,
+            });
+        }
+
+        Ok(timers)
+    }
+
+    async fn mark_timer_fired(&self, timer_id: Uuid) -> Result<(), StorageError> {
+        sqlx::query(
+            "UPDATE timers
+             SET status = 'fired',
+                 updated_at = CURRENT_TIMESTAMP
+             WHERE id = $1",
+        )
+        .bind(timer_id)
+        .execute(&mut *self.conn.acquire().await?)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+use crate::models::{node::NodeId, Transition};
+use crate::workflow::storage::connection::Connection;
+use crate::workflow::storage::error::StorageError;
+use crate::workflow::storage::TransitionRepository;
+use serde_json::Value as JsonValue;
+use sqlx::Row;
+use uuid::Uuid;
+
+pub struct PostgresTransitionRepository<'a> {
+    conn: Connection<'a>,
+}
+
+impl<'a> PostgresTransitionRepository<'a> {
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl<'a> TransitionRepository for PostgresTransitionRepository<'a> {
+    async fn save_transitions(
+        &self,
+        workflow_id: Uuid,
+        transitions: &[Transition],
+    ) -> Result<(), StorageError> {
+        for transition in transitions {
+            sqlx::query(
+                "INSERT INTO workflow_transitions
+                     (workflow_id, node_id, from_status, to_status, event, gate, error, occurred_at)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8)",
+            )
+            .bind(workflow_id)
+            .bind(transition.node_id.as_str())
+            .bind(transition.from.to_string())
+            .bind(transition.to.to_string())
+            .bind(serde_json::to_value(&transition.event)?)
+            .bind(&transition.gate)
+            .bind(&transition.error)
+            .bind(transition.at)
+            .execute(&mut *self.conn.acquire().await?)
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn get_transitions(&self, workflow_id: Uuid) -> Result<Vec<Transition>, StorageError> {
+        let rows = sqlx::query(
+            "SELECT node_id, from_status, to_status, event, gate, error, occurred_at
+             FROM workflow_transitions
+             WHERE workflow_id = $1
+             ORDER BY id",
+        )
+        .bind(workflow_id)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await?;
+
+        let mut transitions = Vec::new();
+        for row in rows {
+            let from: String = row.try_get("from_status")?;
+            let to: String = row.try_get("to_status")?;
+            let event: JsonValue = row.try_get("event")?;
+            transitions.push(Transition {
+                node_id: NodeId::new(row.try_get::<String, _>("node_id")?),
+                from: from.parse().map_err(StorageError::InvalidData)?,
+                to: to.parse().map_err(StorageError::InvalidData)?,
+                event: serde_json::from_value(event)?,
+                gate: row.try_get("gate")?,
+                error: row.try_get("error")?,
+                at: row.try_get("occurred_at")?,
+            });
+        }
+
+        Ok(transitions)
+    }
+}
@@ -1,14 +1,15 @@
+use crate::workflow::storage::connection::Connection;
 use crate::workflow::storage::{StorageError, UserRepository};
-use sqlx::{Executor, PgPool};
+use sqlx::Executor;
 use uuid::Uuid;
 
 pub struct PostgresUserRepository<'a> {
-    pool: &'a PgPool,
+    conn: Connection<'a>,
 }
 
 impl<'a> PostgresUserRepository<'a> {
-    pub fn new(pool: &'a PgPool) -> Self {
-        Self { pool }
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
     }
 }
 
@@ -16,7 +17,9 @@ impl<'a> PostgresUserRepository<'a> {
 impl<'a> UserRepository for PostgresUserRepository<'a> {
     async fn create_user(&self, user_id: Uuid, name: &str) -> Result<(), StorageError> {
         let query = "INSERT INTO users (id, name) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING";
-        self.pool
+        self.conn
+            .acquire()
+            .await?
             .execute(sqlx::query(query).bind(user_id).bind(name))
             .await?;
         Ok(())
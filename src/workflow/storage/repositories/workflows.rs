@@ -1,40 +1,91 @@
-use crate::models::Workflow;
+use crate::models::{workflow::DefinitionRef, CorrelationKey, Workflow};
+use crate::workflow::storage::connection::Connection;
 use crate::workflow::storage::error::StorageError;
 use crate::workflow::storage::WorkflowRepository;
-use sqlx::{PgPool, Row};
+use sqlx::postgres::PgRow;
+use sqlx::Row;
 use uuid::Uuid;
 
 pub struct PostgresWorkflowRepository<'a> {
-    pool: &'a PgPool,
+    conn: Connection<'a>,
 }
 
 impl<'a> PostgresWorkflowRepository<'a> {
-    pub fn new(pool: &'a PgPool) -> Self {
-        Self { pool }
+    pub fn new(conn: impl Into<Connection<'a>>) -> Self {
+        Self { conn: conn.into() }
     }
 }
 
 #[async_trait::async_trait]
 impl<'a> WorkflowRepository for PostgresWorkflowRepository<'a> {
-    async fn save_workflow(&self, workflow: &Workflow) -> Result<(), StorageError> {
+    async fn save_workflow(&self, workflow: &mut Workflow) -> Result<(), StorageError> {
         let bytes = workflow.to_bytes()?;
+        let definition_name = workflow.definition.as_ref().map(|d| d.name.as_str());
+        let definition_version = workflow.definition.as_ref().map(|d| d.version);
+        let failure_reason = workflow.failure.as_ref().map(|f| f.to_string());
+        let parent_id = workflow.parent.as_ref().map(|p| p.workflow_id);
+        let parent_node_id = workflow.parent.as_ref().map(|p| p.node_id.as_str());
+        let correlation_key = workflow
+            .correlation_key
+            .as_ref()
+            .map(CorrelationKey::as_str);
 
-        sqlx::query(
-            "INSERT INTO workflows (id, user_id, name, data, status)
-             VALUES ($1, $2, $3, $4, $5)
-             ON CONFLICT (id) DO UPDATE 
-             SET data = EXCLUDED.data,
-                 status = EXCLUDED.status,
-                 updated_at = CURRENT_TIMESTAMP",
-        )
-        .bind(workflow.id)
-        .bind(workflow.user_id)
-        .bind(&workflow.name)
-        .bind(&bytes)
-        .bind(workflow.status.to_string())
-        .execute(self.pool)
-        .await?;
+        // A workflow that was never saved must not replace an existing row,
+        // and a loaded one only overwrites the version it was read at.
+        let result = if workflow.version == 0 {
+            sqlx::query(
+                "INSERT INTO workflows
+                     (id, user_id, name, data, status, version, definition_name, definition_version,
+                      failure_reason, parent_workflow_id, parent_node_id, correlation_key)
+                 VALUES ($1, $2, $3, $4, $5, 1, $6, $7, $8, $9, $10, $11)
+                 ON CONFLICT (id) DO NOTHING",
+            )
+            .bind(workflow.id)
+            .bind(workflow.user_id)
+            .bind(&workflow.name)
+            .bind(&bytes)
+            .bind(workflow.status.to_string())
+            .bind(definition_name)
+            .bind(definition_version)
+            .bind(&failure_reason)
+            .bind(parent_id)
+            .bind(parent_node_id)
+            .bind(correlation_key)
+            .execute(&mut *self.conn.acquire().await?)
+            .await?
+        } else {
+            sqlx::query(
+                "UPDATE workflows
+                 SET data = $2,
+                     status = $3,
+                     version = version + 1,
+                     definition_name = $5,
+                     definition_version = $6,
+                     failure_reason = $7,
+                     correlation_key = $8,
+                     updated_at = CURRENT_TIMESTAMP
+                 WHERE id = $1 AND version = $4",
+            )
+            .bind(workflow.id)
+            .bind(&bytes)
+            .bind(workflow.status.to_string())
+            .bind(workflow.version)
+            .bind(definition_name)
+            .bind(definition_version)
+            .bind(&failure_reason)
+            .bind(correlation_key)
+            .execute(&mut *self.conn.acquire().await?)
+            .await?
+        };
 
+        if result.rows_affected() == 0 {
+            return Err(StorageError::Conflict {
+                workflow_id: workflow.id,
+                expected: workflow.version,
+            });
+        }
+
+        workflow.version += 1;
         Ok(())
     }
 
@@ -43,18 +94,14 @@ impl<'a> WorkflowRepository for PostgresWorkflowRepository<'a> {
         user_id: Uuid,
         workflow_id: Uuid,
     ) -> Result<Option<Workflow>, StorageError> {
-        let row = sqlx::query("SELECT data FROM workflows WHERE id = $1 AND user_id = $2")
+        let row = sqlx::query("SELECT data, version FROM workflows WHERE id = $1 AND user_id = $2")
             .bind(workflow_id)
             .bind(user_id)
-            .fetch_optional(self.pool)
+            .fetch_optional(&mut *self.conn.acquire().await?)
             .await?;
 
         match row {
-            Some(row) => {
-                let bytes: Vec<u8> = row.get("data");
-                let workflow = Workflow::from_bytes(&bytes)?;
-                Ok(Some(workflow))
-            }
+            Some(row) => Ok(Some(decode_workflow(&row)?)),
             None => Ok(None),
         }
     }
@@ -63,17 +110,38 @@ impl<'a> WorkflowRepository for PostgresWorkflowRepository<'a> {
         &self,
         user_id: Uuid,
     ) -> Result<Vec<Workflow>, StorageError> {
-        let rows =
-            sqlx::query("SELECT data FROM workflows WHERE user_id = $1 AND status = 'active'")
-                .bind(user_id)
-                .fetch_all(self.pool)
-                .await?;
+        let rows = sqlx::query(
+            "SELECT data, version FROM workflows WHERE user_id = $1 AND status = 'active'",
+        )
+        .bind(user_id)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await?;
 
         let mut workflows = Vec::new();
         for row in rows {
-            let bytes: Vec<u8> = row.get("data");
-            let workflow = Workflow::from_bytes(&bytes)?;
-            workflows.push(workflow);
+            workflows.push(decode_workflow(&row)?);
+        }
+
+        Ok(workflows)
+    }
+
+    async fn get_active_workflows_for_correlation(
+        &self,
+        user_id: Uuid,
+        correlation_key: &CorrelationKey,
+    ) -> Result<Vec<Workflow>, StorageError> {
+        let rows = sqlx::query(
+            "SELECT data, version FROM workflows
+             WHERE user_id = $1 AND correlation_key = $2 AND status = 'active'",
+        )
+        .bind(user_id)
+        .bind(correlation_key.as_str())
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await?;
+
+        let mut workflows = Vec::new();
+        for row in rows {
+            workflows.push(decode_workflow(&row)?);
         }
 
         Ok(workflows)
@@ -81,7 +149,7 @@ impl<'a> WorkflowRepository for PostgresWorkflowRepository<'a> {
 
     async fn get_all_workflows(&self) -> Result<Vec<(Uuid, Uuid, String, String)>, StorageError> {
         let rows = sqlx::query("SELECT id, user_id, name, status FROM workflows")
-            .fetch_all(self.pool)
+            .fetch_all(&mut *self.conn.acquire().await?)
             .await?;
 
         let mut workflows = Vec::new();
@@ -96,4 +164,32 @@ impl<'a> WorkflowRepository for PostgresWorkflowRepository<'a> {
 
         Ok(workflows)
     }
+
+    async fn get_active_workflows_for_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Vec<Workflow>, StorageError> {
+        let rows = sqlx::query(
+            "SELECT data, version FROM workflows
+             WHERE definition_name = $1 AND definition_version = $2 AND status = 'active'",
+        )
+        .bind(&definition.name)
+        .bind(definition.version)
+        .fetch_all(&mut *self.conn.acquire().await?)
+        .await?;
+
+        let mut workflows = Vec::new();
+        for row in rows {
+            workflows.push(decode_workflow(&row)?);
+        }
+
+        Ok(workflows)
+    }
+}
+
+fn decode_workflow(row: &PgRow) -> Result<Workflow, StorageError> {
+    let bytes: Vec<u8> = row.get("data");
+    let mut workflow = Workflow::from_bytes(&bytes)?;
+    workflow.version = row.get("version");
+    Ok(workflow)
 }
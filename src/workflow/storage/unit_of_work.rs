@@ -0,0 +1,299 @@
+use crate::models::{
+    workflow::DefinitionRef, ActionRequest, CorrelationKey, Event, OutboxMessage, TimerCommand,
+    Transition, Workflow,
+};
+use crate::workflow::definition::WorkflowDefinition;
+use crate::workflow::storage::error::StorageError;
+use crate::workflow::storage::repositories::{
+    PostgresActionRepository, PostgresDefinitionRepository, PostgresEventRepository,
+    PostgresOutboxRepository, PostgresTimerRepository, PostgresTransitionRepository,
+    PostgresUserRepository, PostgresWorkflowRepository,
+};
+use crate::workflow::storage::{
+    ActionRepository, DefinitionRepository, DueTimer, EventRepository, OutboxEntry,
+    OutboxRepository, PendingAction, StoredEvent, TimerRepository, TransitionRepository,
+    UserRepository, WorkflowRepository,
+};
+use sqlx::{Postgres, Transaction};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// Database transaction shared by every repository it hands out, so a
+/// group of writes either all commit or all roll back. Dropping it without
+/// calling `commit` rolls the transaction back.
+pub struct PostgresUnitOfWork {
+    tx: Mutex<Transaction<'static, Postgres>>,
+}
+
+impl PostgresUnitOfWork {
+    pub fn new(tx: Transaction<'static, Postgres>) -> Self {
+        Self { tx: Mutex::new(tx) }
+    }
+
+    pub async fn commit(self) -> Result<(), StorageError> {
+        self.tx.into_inner().commit().await?;
+        Ok(())
+    }
+
+    pub async fn rollback(self) -> Result<(), StorageError> {
+        self.tx.into_inner().rollback().await?;
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl UserRepository for PostgresUnitOfWork {
+    async fn create_user(&self, user_id: Uuid, name: &str) -> Result<(), StorageError> {
+        PostgresUserRepository::new(&self.tx)
+            .create_user(user_id, name)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl WorkflowRepository for PostgresUnitOfWork {
+    async fn save_workflow(&self, workflow: &mut Workflow) -> Result<(), StorageError> {
+        PostgresWorkflowRepository::new(&self.tx)
+            .save_workflow(workflow)
+            .await
+    }
+
+    async fn load_workflow(
+        &self,
+        user_id: Uuid,
+        workflow_id: Uuid,
+    ) -> Result<Option<Workflow>, StorageError> {
+        PostgresWorkflowRepository::new(&self.tx)
+            .load_workflow(user_id, workflow_id)
+            .await
+    }
+
+    async fn get_active_workflows_for_user(
+        &self,
+        user_id: Uuid,
+    ) -> Result<Vec<Workflow>, StorageError> {
+        PostgresWorkflowRepository::new(&self.tx)
+            .get_active_workflows_for_user(user_id)
+            .await
+    }
+
+    async fn get_active_workflows_for_correlation(
+        &self,
+        user_id: Uuid,
+        correlation_key: &CorrelationKey,
+    ) -> Result<Vec<Workflow>, StorageError> {
+        PostgresWorkflowRepository::new(&self.tx)
+            .get_active_workflows_for_correlation(user_id, correlation_key)
+            .await
+    }
+
+    async fn get_all_workflows(&self) -> Result<Vec<(Uuid, Uuid, String, String)>, StorageError> {
+        PostgresWorkflowRepository::new(&self.tx)
+            .get_all_workflows()
+            .await
+    }
+
+    async fn get_active_workflows_for_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Vec<Workflow>, StorageError> {
+        PostgresWorkflowRepository::new(&self.tx)
+            .get_active_workflows_for_definition(definition)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl DefinitionRepository for PostgresUnitOfWork {
+    async fn save_definition(
+        &self,
+        definition: &WorkflowDefinition,
+    ) -> Result<DefinitionRef, StorageError> {
+        PostgresDefinitionRepository::new(&self.tx)
+            .save_definition(definition)
+            .await
+    }
+
+    async fn load_definition(
+        &self,
+        definition: &DefinitionRef,
+    ) -> Result<Option<WorkflowDefinition>, StorageError> {
+        PostgresDefinitionRepository::new(&self.tx)
+            .load_definition(definition)
+            .await
+    }
+
+    async fn latest_definition(
+        &self,
+        name: &str,
+    ) -> Result<Option<(DefinitionRef, WorkflowDefinition)>, StorageError> {
+        PostgresDefinitionRepository::new(&self.tx)
+            .latest_definition(name)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl EventRepository for PostgresUnitOfWork {
+    async fn save_event(
+        &self,
+        user_id: Uuid,
+        workflow_id: Option<Uuid>,
+        correlation_key: Option<&CorrelationKey>,
+        event: &Event,
+    ) -> Result<(), StorageError> {
+        PostgresEventRepository::new(&self.tx)
+            .save_event(user_id, workflow_id, correlation_key, event)
+            .await
+    }
+
+    async fn get_all_events(
+        &self,
+    ) -> Result<Vec<(Uuid, Uuid, String, serde_json::Value, time::OffsetDateTime)>, StorageError>
+    {
+        PostgresEventRepository::new(&self.tx)
+            .get_all_events()
+            .await
+    }
+
+    async fn get_events_for_user(&self, user_id: Uuid) -> Result<Vec<StoredEvent>, StorageError> {
+        PostgresEventRepository::new(&self.tx)
+            .get_events_for_user(user_id)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl ActionRepository for PostgresUnitOfWork {
+    async fn enqueue_actions(
+        &self,
+        workflow: &Workflow,
+        requests: &[ActionRequest],
+    ) -> Result<(), StorageError> {
+        PostgresActionRepository::new(&self.tx)
+            .enqueue_actions(workflow, requests)
+            .await
+    }
+
+    async fn claim_pending_action(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<PendingAction>, StorageError> {
+        PostgresActionRepository::new(&self.tx)
+            .claim_pending_action(now, lease)
+            .await
+    }
+
+    async fn mark_action_done(
+        &self,
+        action_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError> {
+        PostgresActionRepository::new(&self.tx)
+            .mark_action_done(action_id, lease_token)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl OutboxRepository for PostgresUnitOfWork {
+    async fn enqueue_outbox(
+        &self,
+        workflow: &Workflow,
+        messages: &[OutboxMessage],
+    ) -> Result<(), StorageError> {
+        PostgresOutboxRepository::new(&self.tx)
+            .enqueue_outbox(workflow, messages)
+            .await
+    }
+
+    async fn claim_outbox_entry(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+    ) -> Result<Option<OutboxEntry>, StorageError> {
+        PostgresOutboxRepository::new(&self.tx)
+            .claim_outbox_entry(now, lease)
+            .await
+    }
+
+    async fn mark_outbox_delivered(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+    ) -> Result<bool, StorageError> {
+        PostgresOutboxRepository::new(&self.tx)
+            .mark_outbox_delivered(entry_id, lease_token)
+            .await
+    }
+
+    async fn cancel_outbox_entries(&self, workflow_id: Uuid) -> Result<(), StorageError> {
+        PostgresOutboxRepository::new(&self.tx)
+            .cancel_outbox_entries(workflow_id)
+            .await
+    }
+
+    async fn record_outbox_failure(
+        &self,
+        entry_id: Uuid,
+        lease_token: Uuid,
+        error: &str,
+        retry_at: Option<OffsetDateTime>,
+    ) -> Result<bool, StorageError> {
+        PostgresOutboxRepository::new(&self.tx)
+            .record_outbox_failure(entry_id, lease_token, error, retry_at)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl TimerRepository for PostgresUnitOfWork {
+    async fn apply_timer_commands(
+        &self,
+        workflow: &Workflow,
+        commands: &[TimerCommand],
+    ) -> Result<(), StorageError> {
+        PostgresTimerRepository::new(&self.tx)
+            .apply_timer_commands(workflow, commands)
+            .await
+    }
+
+    async fn claim_due_timers(
+        &self,
+        now: OffsetDateTime,
+        lease: Duration,
+        limit: i64,
+    ) -> Result<Vec<DueTimer>, StorageError> {
+        PostgresTimerRepository::new(&self.tx)
+            .claim_due_timers(now, lease, limit)
+            .await
+    }
+
+    async fn mark_timer_fired(&self, timer_id: Uuid) -> Result<(), StorageError> {
+        PostgresTimerRepository::new(&self.tx)
+            .mark_timer_fired(timer_id)
+            .await
+    }
+}
+
+#[async_trait::async_trait]
+impl TransitionRepository for PostgresUnitOfWork {
+    async fn save_transitions(
+        &self,
+        workflow_id: Uuid,
+        transitions: &[Transition],
+    ) -> Result<(), StorageError> {
+        PostgresTransitionRepository::new(&self.tx)
+            .save_transitions(workflow_id, transitions)
+            .await
+    }
+
+    async fn get_transitions(&self, workflow_id: Uuid) -> Result<Vec<Transition>, StorageError> {
+        PostgresTransitionRepository::new(&self.tx)
+            .get_transitions(workflow_id)
+            .await
+    }
+}
@@ -0,0 +1,121 @@
+use crate::models::{workflow::WorkflowStatus, Event};
+use crate::workflow::dispatcher::{
+    apply, retry_on_conflict, DispatchOutcome, DEFAULT_MAX_ATTEMPTS,
+};
+use crate::workflow::storage::{
+    error::StorageError, DueTimer, EventRepository, TimerRepository, WorkflowRepository,
+};
+use crate::workflow::PostgresStorage;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::task::JoinHandle;
+
+/// Background task that delivers `Event::Timer` to workflows once the
+/// persisted timers they scheduled come due.
+pub struct TimerScheduler {
+    storage: PostgresStorage,
+    poll_interval: Duration,
+    lease: Duration,
+    batch_size: i64,
+    max_attempts: usize,
+}
+
+impl TimerScheduler {
+    pub fn new(storage: PostgresStorage) -> Self {
+        Self {
+            storage,
+            poll_interval: Duration::from_secs(1),
+            lease: Duration::from_secs(60),
+            batch_size: 100,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// How long a claimed timer is hidden from other schedulers before it is
+    /// considered abandoned and delivered again.
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    pub fn with_batch_size(mut self, batch_size: i64) -> Self {
+        self.batch_size = batch_size;
+        self
+    }
+
+    /// How many times a timer is delivered when its workflow keeps being
+    /// saved concurrently before the conflict is reported.
+    pub fn with_max_attempts(mut self, max_attempts: usize) -> Self {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(async move { self.run().await })
+    }
+
+    pub async fn run(&self) {
+        let mut interval = tokio::time::interval(self.poll_interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.fire_due_timers().await {
+                eprintln!("Timer scheduler error: {}", e);
+            }
+        }
+    }
+
+    /// Delivers every timer that is due now and returns how many fired.
+    pub async fn fire_due_timers(&self) -> Result<usize, StorageError> {
+        let due = self
+            .storage
+            .claim_due_timers(OffsetDateTime::now_utc(), self.lease, self.batch_size)
+            .await?;
+
+        let count = due.len();
+        for timer in due {
+            retry_on_conflict(self.max_attempts, || self.fire(&timer)).await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Delivers one timer. The event, the workflow update and marking the
+    /// timer fired commit together, so a crash leaves the timer pending.
+    async fn fire(&self, timer: &DueTimer) -> Result<(), StorageError> {
+        let uow = self.storage.begin().await?;
+        let workflow = uow.load_workflow(timer.user_id, timer.workflow_id).await?;
+
+        if let Some(workflow) = workflow {
+            // Failed and cancelled workflows still take the timers that retry
+            // their compensations
+            let active = workflow.status == WorkflowStatus::Active;
+            if active || workflow.is_compensating() {
+                let event = Event::Timer {
+                    timer_id: timer.timer_id.clone(),
+                };
+                uow.save_event(
+                    timer.user_id,
+                    Some(workflow.id),
+                    workflow.correlation_key.as_ref(),
+                    &event,
+                )
+                .await?;
+                let outcome = apply(&uow, workflow, &event).await?;
+                if let (true, DispatchOutcome::Failed(reason)) = (active, outcome) {
+                    eprintln!(
+                        "Timer {} could not be applied to workflow {}: {}",
+                        timer.timer_id, timer.workflow_id, reason
+                    );
+                }
+            }
+        }
+
+        uow.mark_timer_fired(timer.id).await?;
+        uow.commit().await
+    }
+}
@@ -1,19 +1,21 @@
 use crate::models::{
     edge::Edge,
     event::Event,
-    gate::{Condition, Gate},
-    node::{Node, NodeBehavior, NodeId, NodeStatus},
+    gate::{Condition, EvaluationContext, Gate},
+    node::{BehaviorError, FailurePolicy, Node, NodeBehavior, NodeContext, NodeId, NodeStatus},
+    timer::TimerRequest,
     workflow::Workflow,
 };
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct UserActivityCondition;
 
 #[typetag::serde]
 impl Condition for UserActivityCondition {
-    fn evaluate(&self, event: &Event) -> bool {
-        matches!(event, Event::UserActivity)
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        matches!(context.event, Event::UserActivity)
     }
 }
 
@@ -30,8 +32,8 @@ impl TimerCondition {
 
 #[typetag::serde]
 impl Condition for TimerCondition {
-    fn evaluate(&self, event: &Event) -> bool {
-        matches!(event, Event::Timer { timer_id } if *timer_id == self.timer_id)
+    fn evaluate(&self, context: &EvaluationContext) -> bool {
+        matches!(context.event, Event::Timer { timer_id } if *timer_id == self.timer_id)
     }
 }
 
@@ -40,11 +42,16 @@ pub struct EmptyBehavior;
 
 #[typetag::serde]
 impl NodeBehavior for EmptyBehavior {
-    fn on_activated(&self) -> Option<String> {
-        None
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        Ok(None)
     }
 
-    fn on_completed(&self) {}
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -52,11 +59,16 @@ pub struct TimerNodeBehavior;
 
 #[typetag::serde]
 impl NodeBehavior for TimerNodeBehavior {
-    fn on_activated(&self) -> Option<String> {
-        Some("1".to_string())
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        Ok(Some(TimerRequest::new("1", Duration::from_secs(60))))
     }
 
-    fn on_completed(&self) {}
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -64,39 +76,59 @@ pub struct FinishNodeBehavior;
 
 #[typetag::serde]
 impl NodeBehavior for FinishNodeBehavior {
-    fn on_activated(&self) -> Option<String> {
-        None
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        Ok(None)
     }
 
-    fn on_completed(&self) {
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
         println!("FINISHED");
+        Ok(())
     }
 }
 
 pub fn create_demo_workflow() -> Workflow {
-    let user_activity_node_id = NodeId(0);
-    let timer_node_id = NodeId(1);
-    let finish_node_id = NodeId(2);
+    let user_activity_node_id = NodeId::new("user_activity");
+    let timer_node_id = NodeId::new("timer");
+    let finish_node_id = NodeId::new("finish");
     let nodes = vec![
         Node {
             id: user_activity_node_id,
             name: "User Activity".to_string(),
             status: NodeStatus::Active,
             edges: vec![Edge {
-                target: timer_node_id,
+                target: timer_node_id.clone(),
                 gate: Gate::Single(Box::new(UserActivityCondition)),
             }],
             behavior: Box::new(EmptyBehavior),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
         Node {
             id: timer_node_id,
             name: "Timer".to_string(),
             status: NodeStatus::NotStarted,
             edges: vec![Edge {
-                target: finish_node_id,
+                target: finish_node_id.clone(),
                 gate: Gate::Single(Box::new(TimerCondition::new("1".to_string()))),
             }],
             behavior: Box::new(TimerNodeBehavior),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
         Node {
             id: finish_node_id,
@@ -104,8 +136,16 @@ pub fn create_demo_workflow() -> Workflow {
             status: NodeStatus::NotStarted,
             edges: vec![],
             behavior: Box::new(FinishNodeBehavior),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
     ];
 
-    Workflow::new(nodes)
+    Workflow::try_new(nodes).expect("demo workflow should be valid")
 }
@@ -0,0 +1,194 @@
+use ariadne::models::{
+    action::{
+        ActionContext, ActionError, ActionFailed, ActionHook, ActionResult, ActionSucceeded,
+        NodeAction,
+    },
+    edge::Edge,
+    event::Event,
+    gate::Gate,
+    node::{BehaviorError, FailurePolicy, Node, NodeBehavior, NodeContext, NodeId, NodeStatus},
+    timer::TimerRequest,
+    workflow::{Workflow, WorkflowStatus},
+};
+use ariadne::workflow::actions::run_action;
+use ariadne::workflow::user_activity_workflow::UserActivityCondition;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct NoopBehavior;
+
+#[typetag::serde]
+impl NodeBehavior for NoopBehavior {
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        Ok(None)
+    }
+
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
+}
+
+/// Sleeps for `delay_ms`, then fails if `fail` is set.
+#[derive(Debug, Serialize, Deserialize)]
+struct TestAction {
+    delay_ms: u64,
+    fail: bool,
+}
+
+#[typetag::serde]
+#[async_trait::async_trait]
+impl NodeAction for TestAction {
+    async fn run(&self, context: &ActionContext) -> Result<serde_json::Value, ActionError> {
+        tokio::time::sleep(Duration::from_millis(self.delay_ms)).await;
+        if self.fail {
+            return Err(ActionError::new("smtp unavailable"));
+        }
+        Ok(serde_json::json!({ "sent": context.action_id }))
+    }
+
+    fn timeout(&self) -> Duration {
+        Duration::from_millis(50)
+    }
+}
+
+fn node(id: &str, status: NodeStatus, edges: Vec<Edge>) -> Node {
+    Node {
+        id: NodeId::from(id),
+        name: id.to_string(),
+        status,
+        edges,
+        behavior: Box::new(NoopBehavior),
+        on_failure: FailurePolicy::default(),
+        retry: None,
+        action: None,
+        compensation: None,
+        sub_workflow: None,
+        loop_back: None,
+        activated_at: None,
+        completed_at: None,
+    }
+}
+
+fn context() -> ActionContext {
+    ActionContext {
+        workflow_id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        node_id: NodeId::from("email"),
+        action_id: "email:activated:1".to_string(),
+        hook: ActionHook::Activated,
+    }
+}
+
+/// start -> email, which sends in the background and then moves on to
+/// `sent` or `bounced` depending on the outcome.
+fn email_workflow() -> Workflow {
+    let mut email = node(
+        "email",
+        NodeStatus::NotStarted,
+        vec![
+            Edge {
+                target: NodeId::from("sent"),
+                gate: Gate::Single(Box::new(ActionSucceeded {
+                    node_id: NodeId::from("email"),
+                })),
+            },
+            Edge {
+                target: NodeId::from("bounced"),
+                gate: Gate::Single(Box::new(ActionFailed {
+                    node_id: NodeId::from("email"),
+                })),
+            },
+        ],
+    );
+    email.action = Some(Box::new(TestAction {
+        delay_ms: 0,
+        fail: false,
+    }));
+
+    Workflow::new(vec![
+        node(
+            "start",
+            NodeStatus::Active,
+            vec![Edge {
+                target: NodeId::from("email"),
+                gate: Gate::Single(Box::new(UserActivityCondition)),
+            }],
+        ),
+        email,
+        node("sent", NodeStatus::NotStarted, vec![]),
+        node("bounced", NodeStatus::NotStarted, vec![]),
+    ])
+}
+
+#[test]
+fn test_activation_requests_action_and_result_moves_workflow() {
+    let mut workflow = email_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    let requests = workflow.take_action_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].node_id, NodeId::from("email"));
+    assert_eq!(requests[0].action_id, "email:activated:1");
+    assert_eq!(requests[0].hook, ActionHook::Activated);
+    assert!(workflow.take_action_requests().is_empty());
+
+    // The same events request the same action ids when replayed
+    let mut replayed = email_workflow();
+    replayed.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(replayed.take_action_requests(), requests);
+
+    let status = |w: &Workflow, id: &str| w.node(&NodeId::from(id)).unwrap().status;
+    // A result for an action the node did not request this time is ignored
+    workflow
+        .process_event(&Event::Action {
+            node_id: NodeId::from("email"),
+            action_id: "email:activated:7".to_string(),
+            result: ActionResult::Succeeded(serde_json::Value::Null),
+        })
+        .unwrap();
+    assert_eq!(status(&workflow, "sent"), NodeStatus::NotStarted);
+
+    workflow
+        .process_event(&Event::Action {
+            node_id: NodeId::from("email"),
+            action_id: requests[0].action_id.clone(),
+            result: ActionResult::TimedOut,
+        })
+        .unwrap();
+    assert_eq!(status(&workflow, "bounced"), NodeStatus::Completed);
+    assert!(workflow.pending_actions.is_empty());
+    assert_eq!(status(&workflow, "sent"), NodeStatus::NotStarted);
+    assert_eq!(workflow.status, WorkflowStatus::Active);
+}
+
+#[tokio::test]
+async fn test_run_action_outcomes() {
+    let ok = TestAction {
+        delay_ms: 0,
+        fail: false,
+    };
+    assert_eq!(
+        run_action(&ok, &context()).await,
+        ActionResult::Succeeded(serde_json::json!({ "sent": "email:activated:1" }))
+    );
+
+    let failing = TestAction {
+        delay_ms: 0,
+        fail: true,
+    };
+    assert_eq!(
+        run_action(&failing, &context()).await,
+        ActionResult::Failed("smtp unavailable".to_string())
+    );
+
+    let slow = TestAction {
+        delay_ms: 1_000,
+        fail: false,
+    };
+    assert_eq!(run_action(&slow, &context()).await, ActionResult::TimedOut);
+}
@@ -0,0 +1,116 @@
+use ariadne::models::{event::Event, gate::Gate, node::NodeStatus, workflow::WorkflowStatus};
+use ariadne::workflow::definition::{
+    load_workflow, write_workflow, DefinitionError, WorkflowDefinition,
+};
+use std::time::Duration;
+
+const USER_ACTIVITY: &str = include_str!("../definitions/user_activity.json");
+
+#[test]
+fn test_load_definition() {
+    let mut workflow = load_workflow(USER_ACTIVITY).unwrap();
+
+    assert_eq!(workflow.name, "user_activity");
+    assert_eq!(workflow.nodes.len(), 3);
+    assert_eq!(workflow.nodes[0].status, NodeStatus::Active);
+    assert_eq!(workflow.nodes[1].status, NodeStatus::NotStarted);
+    assert!(workflow.nodes[2].edges.is_empty());
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+    workflow
+        .process_event(&Event::Timer {
+            timer_id: "1".to_string(),
+        })
+        .unwrap();
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_definition_round_trip() {
+    let workflow = load_workflow(USER_ACTIVITY).unwrap();
+    let written = write_workflow(&workflow).unwrap();
+
+    let expected: serde_json::Value = serde_json::from_str(USER_ACTIVITY).unwrap();
+    let actual: serde_json::Value = serde_json::from_str(&written).unwrap();
+    assert_eq!(expected, actual);
+
+    let definition = WorkflowDefinition::from_json(&written).unwrap();
+    assert_eq!(definition.to_json().unwrap(), written);
+}
+
+#[test]
+fn test_unknown_behavior_is_rejected() {
+    let json = r#"{
+        "name": "broken",
+        "nodes": [{ "id": "start", "name": "Start", "behavior": { "type": "NoSuchBehavior" } }]
+    }"#;
+
+    assert!(load_workflow(json).is_err());
+}
+
+#[test]
+fn test_invalid_graph_is_rejected() {
+    let json = r#"{
+        "name": "broken",
+        "nodes": [{
+            "id": "start",
+            "name": "Start",
+            "initial": true,
+            "behavior": { "type": "EmptyBehavior" },
+            "edges": [{ "target": "missing", "gate": { "Single": { "type": "UserActivityCondition" } } }]
+        }]
+    }"#;
+
+    assert!(matches!(
+        load_workflow(json),
+        Err(DefinitionError::Invalid(errors)) if errors.len() == 1
+    ));
+}
+
+#[test]
+fn test_durations_accept_units_and_milliseconds() {
+    let json = r#"{
+        "name": "durations",
+        "nodes": [
+            {
+                "id": "start",
+                "name": "Start",
+                "initial": true,
+                "behavior": { "type": "EmptyBehavior" },
+                "retry": { "max_attempts": 3, "initial_backoff": "5s", "max_backoff": 90000 },
+                "edges": [{ "target": "finish", "gate": { "DeadlineElapsed": "3d" } }]
+            },
+            {
+                "id": "finish",
+                "name": "Finish",
+                "behavior": { "type": "FinishNodeBehavior" },
+                "retry": { "max_attempts": 2, "initial_backoff": { "secs": 1, "nanos": 500000000 } }
+            }
+        ]
+    }"#;
+
+    let workflow = load_workflow(json).unwrap();
+    let retry = workflow.nodes[0].retry.as_ref().unwrap();
+    assert_eq!(retry.initial_backoff, Duration::from_secs(5));
+    assert_eq!(retry.max_backoff, Duration::from_secs(90));
+    assert!(matches!(
+        workflow.nodes[0].edges[0].gate,
+        Gate::DeadlineElapsed(limit) if limit == Duration::from_secs(3 * 86_400)
+    ));
+    let retry = workflow.nodes[1].retry.as_ref().unwrap();
+    assert_eq!(retry.initial_backoff, Duration::from_millis(1500));
+
+    let written: serde_json::Value =
+        serde_json::from_str(&write_workflow(&workflow).unwrap()).unwrap();
+    assert_eq!(written["nodes"][0]["retry"]["initial_backoff"], "5s");
+    assert_eq!(written["nodes"][0]["retry"]["max_backoff"], "90s");
+    assert_eq!(
+        written["nodes"][0]["edges"][0]["gate"]["DeadlineElapsed"],
+        "3d"
+    );
+    assert_eq!(written["nodes"][1]["retry"]["initial_backoff"], "1500ms");
+
+    let invalid = json.replace(r#""5s""#, r#""5 fortnights""#);
+    let error = load_workflow(&invalid).unwrap_err().to_string();
+    assert!(error.contains("invalid duration"), "{}", error);
+}
@@ -0,0 +1,53 @@
+use ariadne::workflow::dispatcher::retry_on_conflict;
+use ariadne::workflow::storage::error::StorageError;
+use std::cell::Cell;
+use uuid::Uuid;
+
+fn conflict() -> StorageError {
+    StorageError::Conflict {
+        workflow_id: Uuid::nil(),
+        expected: 1,
+    }
+}
+
+#[tokio::test]
+async fn test_retry_on_conflict_retries_until_success() {
+    let attempts = Cell::new(0);
+    let result = retry_on_conflict(3, || {
+        attempts.set(attempts.get() + 1);
+        let attempt = attempts.get();
+        async move {
+            if attempt < 3 {
+                Err(conflict())
+            } else {
+                Ok(attempt)
+            }
+        }
+    })
+    .await;
+
+    assert_eq!(result.unwrap(), 3);
+}
+
+#[tokio::test]
+async fn test_retry_on_conflict_gives_up() {
+    let attempts = Cell::new(0);
+    let result: Result<(), _> = retry_on_conflict(2, || {
+        attempts.set(attempts.get() + 1);
+        async { Err(conflict()) }
+    })
+    .await;
+
+    assert!(matches!(result, Err(StorageError::Conflict { .. })));
+    assert_eq!(attempts.get(), 2);
+
+    attempts.set(0);
+    let result: Result<(), _> = retry_on_conflict(3, || {
+        attempts.set(attempts.get() + 1);
+        async { Err(StorageError::InvalidData("bad".to_string())) }
+    })
+    .await;
+
+    assert!(matches!(result, Err(StorageError::InvalidData(_))));
+    assert_eq!(attempts.get(), 1);
+}
@@ -0,0 +1,121 @@
+use ariadne::models::{
+    event::Event,
+    expression::ExpressionCondition,
+    gate::{Condition, EvaluationContext},
+    variables::Variables,
+};
+use ariadne::workflow::definition::WorkflowDefinition;
+
+fn purchase(amount: u64) -> Event {
+    Event::Custom {
+        event_type: "purchase".to_string(),
+        payload: serde_json::json!({ "amount": amount, "item": { "sku": "A-1" } }),
+    }
+}
+
+fn passes(expression: &str, event: &Event, variables: &Variables) -> bool {
+    ExpressionCondition::new(expression)
+        .unwrap()
+        .evaluate(&EvaluationContext::new(event, variables))
+}
+
+#[test]
+fn test_expression_reads_event_and_variables() {
+    let mut gold = Variables::new();
+    gold.set("tier", "gold").unwrap();
+    let expression = r#"event.type == "purchase" && event.amount > 100 && vars.tier == "gold""#;
+
+    assert!(passes(expression, &purchase(150), &gold));
+    assert!(!passes(expression, &purchase(50), &gold));
+    assert!(!passes(expression, &purchase(150), &Variables::new()));
+    assert!(!passes(expression, &Event::UserActivity, &gold));
+
+    assert!(passes(
+        r#"event.item.sku == "A-1" && event.amount * 2 >= 300"#,
+        &purchase(150),
+        &gold
+    ));
+    assert!(passes(
+        r#"event.type == "timer" && event.timer_id == "1""#,
+        &Event::Timer {
+            timer_id: "1".to_string()
+        },
+        &gold
+    ));
+    assert!(passes(
+        "!(event.missing != null) || false",
+        &Event::UserActivity,
+        &gold
+    ));
+    // Comparing values of the wrong type at runtime does not pass
+    assert!(!passes("vars.tier > 1", &Event::UserActivity, &gold));
+}
+
+#[test]
+fn test_expression_tells_custom_events_from_built_in_ones() {
+    let variables = Variables::new();
+    let built_in = Event::Timer {
+        timer_id: "1".to_string(),
+    };
+    let custom = Event::Custom {
+        event_type: "timer".to_string(),
+        payload: serde_json::json!({ "timer_id": "1" }),
+    };
+    let expression = r#"event.type == "timer" && event.kind == "timer""#;
+
+    assert!(passes(expression, &built_in, &variables));
+    assert!(!passes(expression, &custom, &variables));
+    assert!(passes(
+        r#"event.type == "timer" && event.kind == "custom""#,
+        &custom,
+        &variables
+    ));
+    assert!(passes(
+        r#"event.kind == "custom" && event.type == "purchase""#,
+        &purchase(1),
+        &variables
+    ));
+}
+
+#[test]
+fn test_expression_rejected_when_built() {
+    let error = |expression: &str| ExpressionCondition::new(expression).unwrap_err();
+
+    assert_eq!(error("event.amount >").column, 15);
+    // Counted in characters, not bytes
+    assert_eq!(error(r#"event.city == "Zürich" &&"#).column, 26);
+    assert_eq!(error("user.tier == 1").column, 1);
+    assert_eq!(error(r#"event.amount > "100" && 1 < "2""#).column, 27);
+    assert!(error("1 && true").message.contains("`&&`"));
+    assert!(error("event.amount + 1").message.contains("boolean"));
+    assert!(error("event.amount = 1").message.contains("`=`"));
+    assert!(error(r#"event.type == "purchase"#)
+        .message
+        .contains("unterminated"));
+}
+
+#[test]
+fn test_definition_with_invalid_expression_fails_to_load() {
+    let definition = |expression: &str| {
+        serde_json::json!({
+            "name": "purchases",
+            "nodes": [
+                {
+                    "id": "start",
+                    "name": "Start",
+                    "initial": true,
+                    "behavior": { "type": "EmptyBehavior" },
+                    "edges": [{
+                        "target": "end",
+                        "gate": { "Single": { "type": "ExpressionCondition", "expression": expression } }
+                    }]
+                },
+                { "id": "end", "name": "End", "behavior": { "type": "EmptyBehavior" } }
+            ]
+        })
+        .to_string()
+    };
+
+    assert!(WorkflowDefinition::from_json(&definition("event.amount > 100")).is_ok());
+    assert!(WorkflowDefinition::from_json(&definition("event.amount >")).is_err());
+}
@@ -0,0 +1,118 @@
+use ariadne::models::{
+    event::Event, node::NodeId, workflow::DefinitionRef, workflow::WorkflowStatus, CorrelationKey,
+    NodeStatus, TimerCommand,
+};
+use ariadne::workflow::definition::WorkflowDefinition;
+use ariadne::workflow::migration::{migrate_workflow, MigrationError, NodeMapping};
+
+const USER_ACTIVITY: &str = include_str!("../definitions/user_activity.json");
+
+fn version(version: i32) -> DefinitionRef {
+    DefinitionRef {
+        name: "user_activity".to_string(),
+        version,
+    }
+}
+
+/// Version 2 renames the timer node to "wait".
+fn renamed_definition() -> WorkflowDefinition {
+    let json = USER_ACTIVITY
+        .replace("\"id\": \"timer\"", "\"id\": \"wait\"")
+        .replace("\"target\": \"timer\"", "\"target\": \"wait\"");
+    WorkflowDefinition::from_json(&json).unwrap()
+}
+
+fn running_instance() -> ariadne::models::Workflow {
+    let mut workflow = WorkflowDefinition::from_json(USER_ACTIVITY)
+        .unwrap()
+        .instantiate(version(1))
+        .unwrap();
+    workflow.version = 4;
+    workflow.process_event(&Event::UserActivity).unwrap();
+    workflow.take_timer_commands();
+    workflow
+}
+
+#[test]
+fn test_migrate_carries_statuses_and_timers() {
+    let workflow = running_instance();
+    let id = workflow.id;
+    let mapping = NodeMapping::new()
+        .map("user_activity", "user_activity")
+        .map("timer", "wait");
+
+    let mut migrated =
+        migrate_workflow(workflow, renamed_definition(), version(2), &mapping).unwrap();
+
+    assert_eq!(migrated.id, id);
+    assert_eq!(migrated.version, 4);
+    assert_eq!(migrated.definition, Some(version(2)));
+    let statuses: Vec<_> = migrated.nodes.iter().map(|n| n.status).collect();
+    assert_eq!(
+        statuses,
+        vec![
+            NodeStatus::Completed,
+            NodeStatus::Active,
+            NodeStatus::NotStarted
+        ]
+    );
+
+    assert_eq!(migrated.scheduled_timers.len(), 1);
+    assert_eq!(migrated.scheduled_timers[0].node_id, NodeId::from("wait"));
+    let commands = migrated.take_timer_commands();
+    assert!(matches!(
+        commands.as_slice(),
+        [TimerCommand::Cancel { node_id }, TimerCommand::Schedule(timer)]
+            if *node_id == NodeId::from("timer") && timer.node_id == NodeId::from("wait")
+    ));
+
+    migrated
+        .process_event(&Event::Timer {
+            timer_id: "1".to_string(),
+        })
+        .unwrap();
+    assert_eq!(migrated.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_migrate_keeps_correlation_key() {
+    let workflow = running_instance().with_correlation_key("order-42");
+    let mapping = NodeMapping::new()
+        .map("user_activity", "user_activity")
+        .map("timer", "wait");
+
+    let migrated = migrate_workflow(workflow, renamed_definition(), version(2), &mapping).unwrap();
+
+    assert_eq!(
+        migrated.correlation_key,
+        Some(CorrelationKey::from("order-42"))
+    );
+}
+
+#[test]
+fn test_migrate_rejects_incomplete_mapping() {
+    let mapping = NodeMapping::new().map("user_activity", "user_activity");
+    let result = migrate_workflow(
+        running_instance(),
+        renamed_definition(),
+        version(2),
+        &mapping,
+    );
+    assert!(matches!(
+        result,
+        Err(MigrationError::UnmappedNode { node, status: NodeStatus::Active })
+            if node == NodeId::from("timer")
+    ));
+
+    let mapping = mapping.map("timer", "timer");
+    let result = migrate_workflow(
+        running_instance(),
+        renamed_definition(),
+        version(2),
+        &mapping,
+    );
+    assert!(matches!(
+        result,
+        Err(MigrationError::UnknownTarget { target, .. }) if target == NodeId::from("timer")
+    ));
+}
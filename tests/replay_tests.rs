@@ -0,0 +1,298 @@
+use ariadne::models::{
+    action::ActionResult,
+    event::{CustomEvent, Event},
+    node::NodeStatus,
+    workflow::{Workflow, WorkflowStatus},
+    CorrelationKey,
+};
+use ariadne::workflow::definition::{load_workflow, WorkflowDefinition};
+use ariadne::workflow::replay::{decode_events, replay_events, replay_workflow, UnknownEvents};
+use ariadne::workflow::storage::{
+    error::StorageError,
+    repositories::events::{decode_event, encode_event},
+    EventRepository, StoredEvent,
+};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+const USER_ACTIVITY: &str = include_str!("../definitions/user_activity.json");
+
+fn stored(event_type: &str, event_data: serde_json::Value) -> StoredEvent {
+    StoredEvent {
+        id: Uuid::new_v4(),
+        user_id: Uuid::new_v4(),
+        event_type: event_type.to_string(),
+        event_data,
+        workflow_id: None,
+        correlation_key: None,
+        created_at: time::OffsetDateTime::now_utc(),
+    }
+}
+
+#[test]
+fn test_event_encoding_round_trip() {
+    for event in [
+        Event::UserActivity,
+        Event::Timer {
+            timer_id: "1".to_string(),
+        },
+        Event::Action {
+            node_id: "email".into(),
+            action_id: "email:activated:1".to_string(),
+            result: ActionResult::Succeeded(serde_json::json!({ "id": 7 })),
+        },
+        Event::Cancelled {
+            reason: "unsubscribed".to_string(),
+        },
+        Event::Custom {
+            event_type: "purchase".to_string(),
+            payload: serde_json::json!({ "amount": 10 }),
+        },
+        Event::ChildFinished {
+            node_id: "onboarding".into(),
+            child_id: Uuid::nil(),
+            status: WorkflowStatus::Failed,
+            reason: Some("node welcome failed: bounced".to_string()),
+        },
+        Event::ChildFinished {
+            node_id: "onboarding".into(),
+            child_id: Uuid::nil(),
+            status: WorkflowStatus::Completed,
+            reason: None,
+        },
+    ] {
+        let (event_type, event_data) = encode_event(&event);
+        assert_eq!(decode_event(&event_type, &event_data).unwrap(), event);
+    }
+}
+
+#[test]
+fn test_decode_rejects_unknown_and_malformed_events() {
+    assert!(matches!(
+        decode_event("purchase", &serde_json::Value::Null),
+        Err(StorageError::UnknownEventType(event_type)) if event_type == "purchase"
+    ));
+    assert!(matches!(
+        decode_event("timer", &serde_json::json!({})),
+        Err(StorageError::InvalidData(_))
+    ));
+}
+
+#[test]
+fn test_decode_events_unknown_policy() {
+    let rows = vec![
+        stored("user_activity", serde_json::Value::Null),
+        stored("purchase", serde_json::json!({ "amount": 10 })),
+        stored("timer", serde_json::json!({ "timer_id": "1" })),
+    ];
+
+    assert!(decode_events(&rows, UnknownEvents::Fail).is_err());
+    assert_eq!(
+        decode_events(&rows, UnknownEvents::Skip).unwrap(),
+        vec![
+            Event::UserActivity,
+            Event::Timer {
+                timer_id: "1".to_string()
+            }
+        ]
+    );
+}
+
+#[test]
+fn test_replay_is_deterministic() {
+    let events = [
+        Event::UserActivity,
+        Event::Timer {
+            timer_id: "1".to_string(),
+        },
+    ];
+
+    let mut first = load_workflow(USER_ACTIVITY).unwrap();
+    let mut second = load_workflow(USER_ACTIVITY).unwrap();
+    replay_events(&mut first, &events[..1]).unwrap();
+    replay_events(&mut second, &events[..1]).unwrap();
+
+    let statuses = |w: &Workflow| w.nodes.iter().map(|n| n.status).collect::<Vec<_>>();
+    assert_eq!(statuses(&first), statuses(&second));
+
+    replay_events(&mut first, &events[1..]).unwrap();
+    assert_eq!(first.status, WorkflowStatus::Completed);
+}
+
+fn instance() -> Workflow {
+    load_workflow(USER_ACTIVITY).unwrap()
+}
+
+#[test]
+fn test_correlated_events_reach_only_their_key() {
+    let plain = instance();
+    let order = instance().with_correlation_key("order-1");
+    let other = instance().with_correlation_key("order-2");
+
+    let uncorrelated = stored("user_activity", serde_json::json!({}));
+    assert!(uncorrelated.reaches(&plain));
+    assert!(uncorrelated.reaches(&order));
+
+    let correlated = StoredEvent {
+        correlation_key: Some(CorrelationKey::from("order-1")),
+        ..stored("user_activity", serde_json::json!({}))
+    };
+    assert!(correlated.reaches(&order));
+    assert!(!correlated.reaches(&other));
+    assert!(!correlated.reaches(&plain));
+}
+
+#[test]
+fn test_events_of_one_workflow_reach_only_it() {
+    let workflow = instance();
+    let timer = StoredEvent {
+        workflow_id: Some(workflow.id),
+        ..stored("timer", serde_json::json!({ "timer_id": "1" }))
+    };
+    assert!(timer.reaches(&workflow));
+    assert!(!timer.reaches(&instance()));
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct Purchase {
+    amount: u64,
+}
+
+impl CustomEvent for Purchase {
+    const EVENT_TYPE: &'static str = "purchase";
+}
+
+#[test]
+fn test_custom_events_decode_to_their_type() {
+    let event = Event::custom(&Purchase { amount: 10 }).unwrap();
+    let (event_type, event_data) = encode_event(&event);
+    assert_eq!(event_type, "custom:purchase");
+
+    let decoded = decode_event(&event_type, &event_data).unwrap();
+    assert_eq!(
+        decoded.as_custom::<Purchase>().unwrap().unwrap(),
+        Purchase { amount: 10 }
+    );
+    assert!(Event::UserActivity.as_custom::<Purchase>().is_none());
+}
+
+/// Event store holding a fixed list of recorded events.
+struct RecordedEvents(Vec<StoredEvent>);
+
+#[async_trait::async_trait]
+impl EventRepository for RecordedEvents {
+    async fn save_event(
+        &self,
+        _user_id: Uuid,
+        _workflow_id: Option<Uuid>,
+        _correlation_key: Option<&CorrelationKey>,
+        _event: &Event,
+    ) -> Result<(), StorageError> {
+        unimplemented!("replay only reads events")
+    }
+
+    async fn get_all_events(
+        &self,
+    ) -> Result<Vec<(Uuid, Uuid, String, serde_json::Value, OffsetDateTime)>, StorageError> {
+        unimplemented!("replay only reads events of one user")
+    }
+
+    async fn get_events_for_user(&self, user_id: Uuid) -> Result<Vec<StoredEvent>, StorageError> {
+        Ok(self
+            .0
+            .iter()
+            .filter(|event| event.user_id == user_id)
+            .cloned()
+            .collect())
+    }
+}
+
+const WINDOWED: &str = r#"{
+    "name": "windowed",
+    "nodes": [
+        {
+            "id": "start",
+            "name": "Start",
+            "initial": true,
+            "behavior": { "type": "EmptyBehavior" },
+            "edges": [{
+                "target": "finish",
+                "gate": { "And": [
+                    { "WithinWindow": "1h" },
+                    { "Single": { "type": "UserActivityCondition" } }
+                ] }
+            }]
+        },
+        { "id": "finish", "name": "Finish", "behavior": { "type": "FinishNodeBehavior" } }
+    ]
+}"#;
+
+#[tokio::test]
+async fn test_replay_measures_initial_windows_from_creation() {
+    let user_id = Uuid::new_v4();
+    let created_at = OffsetDateTime::now_utc() - Duration::from_secs(2 * 86_400);
+    let activity_after = |delay: Duration| StoredEvent {
+        user_id,
+        created_at: created_at + delay,
+        ..stored("user_activity", serde_json::Value::Null)
+    };
+    let replay = |events: Vec<StoredEvent>| async move {
+        replay_workflow(
+            &RecordedEvents(events),
+            WorkflowDefinition::from_json(WINDOWED).unwrap(),
+            user_id,
+            None,
+            None,
+            created_at,
+            UnknownEvents::Fail,
+        )
+        .await
+        .unwrap()
+    };
+
+    let workflow = replay(vec![activity_after(Duration::from_secs(1800))]).await;
+    assert_eq!(workflow.nodes[0].activated_at, Some(created_at));
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+
+    // Outside the window when it was recorded, however long ago that was.
+    let workflow = replay(vec![activity_after(Duration::from_secs(7200))]).await;
+    assert_eq!(workflow.nodes[0].status, NodeStatus::Active);
+    assert_eq!(workflow.status, WorkflowStatus::Active);
+}
+
+#[tokio::test]
+async fn test_cancellation_replays_only_onto_its_workflow() {
+    let user_id = Uuid::new_v4();
+    let cancelled = Uuid::new_v4();
+    let cancellation = StoredEvent {
+        user_id,
+        workflow_id: Some(cancelled),
+        ..stored(
+            "cancelled",
+            serde_json::json!({ "reason": "account closed" }),
+        )
+    };
+    let events = RecordedEvents(vec![cancellation]);
+    let replay = |workflow_id: Uuid| {
+        replay_workflow(
+            &events,
+            WorkflowDefinition::from_json(USER_ACTIVITY).unwrap(),
+            user_id,
+            Some(workflow_id),
+            None,
+            OffsetDateTime::now_utc(),
+            UnknownEvents::Fail,
+        )
+    };
+
+    assert_eq!(
+        replay(cancelled).await.unwrap().status,
+        WorkflowStatus::Cancelled
+    );
+    assert_eq!(
+        replay(Uuid::new_v4()).await.unwrap().status,
+        WorkflowStatus::Active
+    );
+}
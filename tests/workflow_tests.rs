@@ -1,10 +1,21 @@
-use ariadne::{models::{
-    edge::Edge,
-    event::Event,
-    gate::{Condition, Gate},
-    node::{Node, NodeBehavior, NodeId, NodeStatus},
-    workflow::{Workflow, WorkflowStatus},
-}, workflow::user_activity_workflow::UserActivityCondition};
+use ariadne::{
+    models::{
+        compensation::Compensation,
+        edge::{Edge, LoopBack},
+        event::{CustomEventCondition, Event},
+        gate::{deadline_timer_id, Condition, EvaluationContext, Gate, Join},
+        node::{BehaviorError, FailurePolicy, Node, NodeBehavior, NodeContext, NodeId, NodeStatus},
+        outbox::OutboxMessage,
+        retry::RetryPolicy,
+        sub_workflow::{ParentLink, SubWorkflow, SubWorkflowCompleted},
+        timer::{TimerCommand, TimerRequest},
+        validation::ValidationError,
+        variables::VariableEquals,
+        workflow::{Workflow, WorkflowError, WorkflowStatus},
+        CorrelationKey,
+    },
+    workflow::user_activity_workflow::{TimerCondition, UserActivityCondition},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,7 +23,7 @@ struct TestCondition(bool);
 
 #[typetag::serde]
 impl Condition for TestCondition {
-    fn evaluate(&self, _event: &Event) -> bool {
+    fn evaluate(&self, _context: &EvaluationContext) -> bool {
         self.0
     }
 }
@@ -40,15 +51,19 @@ impl Clone for TestBehavior {
 
 #[typetag::serde]
 impl NodeBehavior for TestBehavior {
-    fn on_activated(&self) -> Option<String> {
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
         self.activated_count
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-        None
+        Ok(None)
     }
 
-    fn on_completed(&self) {
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
         self.completed_count
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        Ok(())
     }
 }
 #[test]
@@ -61,26 +76,42 @@ fn test_workflow_activation() {
 
     let nodes = vec![
         Node {
-            id: NodeId(0),
+            id: NodeId::from("start"),
             name: "Start".to_string(),
             status: NodeStatus::Active,
             edges: vec![Edge {
-                target: NodeId(1),
+                target: NodeId::from("end"),
                 gate: Gate::Single(Box::new(UserActivityCondition)),
             }],
             behavior: Box::new(behavior1),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
         Node {
-            id: NodeId(1),
+            id: NodeId::from("end"),
             name: "End".to_string(),
             status: NodeStatus::NotStarted,
             edges: vec![],
             behavior: Box::new(behavior2),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
     ];
 
     let mut workflow = Workflow::new(nodes);
-    workflow.process_event(&Event::UserActivity);
+    workflow.process_event(&Event::UserActivity).unwrap();
 
     assert_eq!(workflow.nodes[0].status, NodeStatus::Completed);
     assert_eq!(workflow.nodes[1].status, NodeStatus::Completed);
@@ -97,26 +128,42 @@ fn test_workflow_blocked_activation() {
 
     let nodes = vec![
         Node {
-            id: NodeId(0),
+            id: NodeId::from("start"),
             name: "Start".to_string(),
             status: NodeStatus::Active,
             edges: vec![Edge {
-                target: NodeId(1),
+                target: NodeId::from("end"),
                 gate: Gate::Single(Box::new(TestCondition(false))),
             }],
             behavior: Box::new(behavior1),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
         Node {
-            id: NodeId(1),
+            id: NodeId::from("end"),
             name: "End".to_string(),
             status: NodeStatus::NotStarted,
             edges: vec![],
             behavior: Box::new(behavior2),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
     ];
 
     let mut workflow = Workflow::new(nodes);
-    workflow.process_event(&Event::UserActivity);
+    workflow.process_event(&Event::UserActivity).unwrap();
 
     assert_eq!(workflow.nodes[0].status, NodeStatus::Active);
     assert_eq!(workflow.nodes[1].status, NodeStatus::NotStarted);
@@ -133,21 +180,37 @@ fn test_workflow_serialization() {
 
     let nodes = vec![
         Node {
-            id: NodeId(0),
+            id: NodeId::from("start"),
             name: "Start".to_string(),
             status: NodeStatus::Active,
             edges: vec![Edge {
-                target: NodeId(1),
+                target: NodeId::from("end"),
                 gate: Gate::Single(Box::new(TestCondition(true))),
             }],
             behavior: Box::new(behavior1),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
         Node {
-            id: NodeId(1),
+            id: NodeId::from("end"),
             name: "End".to_string(),
             status: NodeStatus::NotStarted,
             edges: vec![],
             behavior: Box::new(behavior2),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
         },
     ];
 
@@ -160,3 +223,1688 @@ fn test_workflow_serialization() {
     assert_eq!(workflow.status, deserialized.status);
     assert_eq!(workflow.nodes.len(), deserialized.nodes.len());
 }
+
+#[test]
+fn test_correlation_key_survives_serialization() {
+    let workflow = linear_workflow(2).with_correlation_key("order-42");
+    let bytes = workflow.to_bytes().unwrap();
+    let deserialized = Workflow::from_bytes(&bytes).unwrap();
+
+    assert_eq!(
+        deserialized.correlation_key,
+        Some(CorrelationKey::from("order-42"))
+    );
+
+    let bytes = linear_workflow(2).to_bytes().unwrap();
+    assert_eq!(Workflow::from_bytes(&bytes).unwrap().correlation_key, None);
+}
+
+/// Demo workflow saved by the original bincode encoding after a user
+/// activity, on a little-endian machine.
+const BASELINE_BLOB: &[u8] = include_bytes!("fixtures/workflow_baseline.bin");
+
+#[test]
+fn test_baseline_blob_still_decodes() {
+    let mut workflow = Workflow::from_bytes(BASELINE_BLOB).unwrap();
+
+    assert_eq!(
+        workflow.id.to_string(),
+        "5f0c6a3e-8d2b-4f7a-9c1e-2b3d4e5f6a7b"
+    );
+    assert_eq!(workflow.name, "user_activity");
+    assert_eq!(workflow.status, WorkflowStatus::Active);
+    assert_eq!(status_of(&workflow, "0"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "1"), NodeStatus::Active);
+    assert_eq!(status_of(&workflow, "2"), NodeStatus::NotStarted);
+    assert_eq!(workflow.nodes[1].edges[0].target, NodeId::from("2"));
+
+    let bytes = workflow.to_bytes().unwrap();
+    assert!(bytes.starts_with(ariadne::models::workflow::BLOB_MAGIC));
+    workflow = Workflow::from_bytes(&bytes).unwrap();
+    workflow
+        .process_event(&Event::Timer {
+            timer_id: "1".to_string(),
+        })
+        .unwrap();
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_blob_without_newer_fields_decodes() {
+    let workflow = linear_workflow(2);
+    let bytes = workflow.to_bytes().unwrap();
+    let (header, body) = bytes.split_at(5);
+
+    // A blob written before these fields existed.
+    let mut value: serde_json::Value = serde_json::from_slice(body).unwrap();
+    let fields = value.as_object_mut().unwrap();
+    fields.remove("pending_actions");
+    fields.remove("iterations");
+    fields.remove("max_steps");
+    for node in fields["nodes"].as_array_mut().unwrap() {
+        node.as_object_mut().unwrap().remove("completed_at");
+    }
+    let mut older = header.to_vec();
+    older.extend(serde_json::to_vec(&value).unwrap());
+
+    let mut restored = Workflow::from_bytes(&older).unwrap();
+    assert!(restored.pending_actions.is_empty());
+    assert_eq!(restored.max_steps, workflow.max_steps);
+    restored.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(restored.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_blob_with_unknown_version_is_rejected() {
+    let mut bytes = linear_workflow(1).to_bytes().unwrap();
+    bytes[4] = 99;
+    assert!(matches!(
+        Workflow::from_bytes(&bytes),
+        Err(ariadne::models::workflow::BlobError::UnsupportedVersion(99))
+    ));
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TestTimerBehavior(String);
+
+#[typetag::serde]
+impl NodeBehavior for TestTimerBehavior {
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        Ok(Some(TimerRequest::new(
+            self.0.clone(),
+            std::time::Duration::from_secs(3600),
+        )))
+    }
+
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
+}
+
+fn create_timer_workflow() -> Workflow {
+    let nodes = vec![
+        Node {
+            id: NodeId::from("start"),
+            name: "Start".to_string(),
+            status: NodeStatus::Active,
+            edges: vec![Edge {
+                target: NodeId::from("wait"),
+                gate: Gate::Single(Box::new(UserActivityCondition)),
+            }],
+            behavior: Box::new(TestBehavior {
+                activated_count: std::sync::atomic::AtomicUsize::new(0),
+                completed_count: std::sync::atomic::AtomicUsize::new(0),
+            }),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
+        },
+        Node {
+            id: NodeId::from("wait"),
+            name: "Wait".to_string(),
+            status: NodeStatus::NotStarted,
+            edges: vec![Edge {
+                target: NodeId::from("end"),
+                gate: Gate::Or(vec![
+                    Gate::Single(Box::new(TimerCondition::new("reminder".to_string()))),
+                    Gate::Single(Box::new(TimerCondition::new("escalate".to_string()))),
+                ]),
+            }],
+            behavior: Box::new(TestTimerBehavior("reminder".to_string())),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
+        },
+        Node {
+            id: NodeId::from("end"),
+            name: "End".to_string(),
+            status: NodeStatus::NotStarted,
+            edges: vec![],
+            behavior: Box::new(TestBehavior {
+                activated_count: std::sync::atomic::AtomicUsize::new(0),
+                completed_count: std::sync::atomic::AtomicUsize::new(0),
+            }),
+            on_failure: FailurePolicy::default(),
+            retry: None,
+            action: None,
+            compensation: None,
+            sub_workflow: None,
+            loop_back: None,
+            activated_at: None,
+            completed_at: None,
+        },
+    ];
+
+    Workflow::new(nodes)
+}
+
+#[test]
+fn test_timer_scheduled_and_fired() {
+    let mut workflow = create_timer_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    let commands = workflow.take_timer_commands();
+    assert_eq!(commands.len(), 1);
+    assert!(matches!(
+        &commands[0],
+        TimerCommand::Schedule(timer) if timer.timer_id == "reminder" && timer.node_id == NodeId::from("wait")
+    ));
+    assert_eq!(workflow.scheduled_timers.len(), 1);
+
+    workflow
+        .process_event(&Event::Timer {
+            timer_id: "reminder".to_string(),
+        })
+        .unwrap();
+
+    assert!(workflow.scheduled_timers.is_empty());
+    assert!(workflow.take_timer_commands().is_empty());
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_timer_cancelled_when_node_completes_another_way() {
+    let mut workflow = create_timer_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+    workflow.take_timer_commands();
+
+    workflow
+        .process_event(&Event::Timer {
+            timer_id: "escalate".to_string(),
+        })
+        .unwrap();
+
+    assert_eq!(workflow.nodes[1].status, NodeStatus::Completed);
+    assert!(workflow.scheduled_timers.is_empty());
+    assert_eq!(
+        workflow.take_timer_commands(),
+        vec![TimerCommand::Cancel {
+            node_id: NodeId::from("wait")
+        }]
+    );
+}
+
+fn plain_node(id: &str, status: NodeStatus, edges: Vec<Edge>) -> Node {
+    Node {
+        id: NodeId::from(id),
+        name: id.to_string(),
+        status,
+        edges,
+        behavior: Box::new(TestBehavior {
+            activated_count: std::sync::atomic::AtomicUsize::new(0),
+            completed_count: std::sync::atomic::AtomicUsize::new(0),
+        }),
+        on_failure: FailurePolicy::default(),
+        retry: None,
+        action: None,
+        compensation: None,
+        sub_workflow: None,
+        loop_back: None,
+        activated_at: None,
+        completed_at: None,
+    }
+}
+
+fn edge_to(target: &str, gate: Gate) -> Edge {
+    Edge {
+        target: NodeId::from(target),
+        gate,
+    }
+}
+
+#[test]
+fn test_validate_joins() {
+    let workflow = Workflow::new(vec![
+        plain_node(
+            "a",
+            NodeStatus::Active,
+            vec![
+                edge_to("b", Gate::Single(Box::new(TestCondition(true)))),
+                edge_to("c", Gate::Single(Box::new(TestCondition(true)))),
+            ],
+        ),
+        plain_node(
+            "b",
+            NodeStatus::NotStarted,
+            vec![edge_to(
+                "d",
+                Gate::WaitForNodes(vec![NodeId::from("b"), NodeId::from("c")]),
+            )],
+        ),
+        plain_node("c", NodeStatus::NotStarted, vec![]),
+        plain_node("d", NodeStatus::NotStarted, vec![]),
+    ]);
+
+    assert_eq!(
+        workflow.validate(),
+        Err(vec![ValidationError::JoinCannotPrecede {
+            node: NodeId::from("b"),
+            target: NodeId::from("d"),
+            waits_on: NodeId::from("b"),
+        }])
+    );
+
+    let workflow = Workflow::new(vec![
+        plain_node(
+            "a",
+            NodeStatus::Active,
+            vec![
+                edge_to("b", Gate::Single(Box::new(TestCondition(true)))),
+                edge_to("c", Gate::Single(Box::new(TestCondition(true)))),
+            ],
+        ),
+        plain_node("b", NodeStatus::NotStarted, vec![]),
+        plain_node(
+            "c",
+            NodeStatus::NotStarted,
+            vec![edge_to("d", Gate::WaitForNodes(vec![NodeId::from("b")]))],
+        ),
+        plain_node("d", NodeStatus::NotStarted, vec![]),
+    ]);
+
+    assert_eq!(workflow.validate(), Ok(()));
+}
+
+#[test]
+fn test_validate_reports_graph_errors() {
+    let workflow = Workflow::new(vec![
+        plain_node(
+            "a",
+            NodeStatus::NotStarted,
+            vec![
+                edge_to("b", Gate::Single(Box::new(TestCondition(true)))),
+                edge_to("missing", Gate::WaitForNodes(vec![NodeId::from("unknown")])),
+            ],
+        ),
+        plain_node(
+            "b",
+            NodeStatus::NotStarted,
+            vec![edge_to("a", Gate::Single(Box::new(TestCondition(true))))],
+        ),
+        plain_node("b", NodeStatus::NotStarted, vec![]),
+    ]);
+
+    let errors = workflow.validate().unwrap_err();
+
+    assert!(errors.contains(&ValidationError::DuplicateNodeId(NodeId::from("b"))));
+    assert!(errors.contains(&ValidationError::DanglingEdge {
+        node: NodeId::from("a"),
+        target: NodeId::from("missing"),
+    }));
+    assert!(errors.contains(&ValidationError::UnknownJoinNode {
+        node: NodeId::from("a"),
+        waits_on: NodeId::from("unknown"),
+    }));
+    assert!(errors.contains(&ValidationError::NoInitialNode));
+    assert!(errors.contains(&ValidationError::UnreachableNode(NodeId::from("a"))));
+    assert!(errors.contains(&ValidationError::Cycle {
+        node: NodeId::from("b"),
+        target: NodeId::from("a"),
+    }));
+    assert!(Workflow::try_new(Vec::new()).is_err());
+}
+
+#[test]
+fn test_wait_for_missing_node_does_not_panic() {
+    let mut workflow = Workflow::new(vec![
+        plain_node(
+            "a",
+            NodeStatus::Active,
+            vec![edge_to(
+                "b",
+                Gate::WaitForNodes(vec![NodeId::from("missing")]),
+            )],
+        ),
+        plain_node("b", NodeStatus::NotStarted, vec![]),
+    ]);
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    assert_eq!(workflow.nodes[0].status, NodeStatus::Active);
+}
+
+#[test]
+fn test_join_independent_of_node_order() {
+    // Nodes are listed in an order that does not match their ids
+    let workflow = Workflow::new(vec![
+        plain_node("join", NodeStatus::NotStarted, vec![]),
+        plain_node(
+            "left",
+            NodeStatus::NotStarted,
+            vec![edge_to(
+                "join",
+                Gate::WaitForNodes(vec![NodeId::from("right")]),
+            )],
+        ),
+        plain_node("right", NodeStatus::NotStarted, vec![]),
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![
+                edge_to("right", Gate::Single(Box::new(TestCondition(true)))),
+                edge_to("left", Gate::Single(Box::new(TestCondition(true)))),
+            ],
+        ),
+    ]);
+    let mut workflow = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    assert_eq!(workflow.validate(), Ok(()));
+    assert_eq!(
+        workflow.node(&NodeId::from("join")).unwrap().status,
+        NodeStatus::Completed
+    );
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+fn linear_workflow(size: usize) -> Workflow {
+    let nodes = (0..size)
+        .map(|i| {
+            let edges = if i + 1 < size {
+                vec![edge_to(
+                    &(i + 1).to_string(),
+                    Gate::Single(Box::new(TestCondition(true))),
+                )]
+            } else {
+                vec![]
+            };
+            let status = if i == 0 {
+                NodeStatus::Active
+            } else {
+                NodeStatus::NotStarted
+            };
+            plain_node(&i.to_string(), status, edges)
+        })
+        .collect();
+
+    Workflow::new(nodes)
+}
+
+#[test]
+fn test_long_chain_does_not_overflow_stack() {
+    let mut workflow = linear_workflow(100_000);
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_step_limit() {
+    let mut workflow = linear_workflow(10);
+    workflow.max_steps = 5;
+
+    assert_eq!(
+        workflow.process_event(&Event::UserActivity),
+        Err(WorkflowError::StepLimitExceeded(5))
+    );
+    assert_eq!(workflow.status, WorkflowStatus::Active);
+}
+
+#[test]
+fn test_journal_records_transitions() {
+    let mut workflow = create_timer_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    let journal = workflow.take_journal();
+    assert_eq!(journal.len(), 2);
+    assert_eq!(journal[1].node_id, NodeId::from("start"));
+    assert_eq!(journal[1].to, NodeStatus::Completed);
+    assert_eq!(journal[0].node_id, NodeId::from("wait"));
+    assert_eq!(journal[0].from, NodeStatus::NotStarted);
+    assert_eq!(journal[0].to, NodeStatus::Active);
+    assert_eq!(journal[0].event, Event::UserActivity);
+    assert_eq!(
+        journal[0].gate,
+        Some(serde_json::json!({ "Single": { "type": "UserActivityCondition" } }))
+    );
+    assert!(workflow.journal().is_empty());
+
+    let timer = Event::Timer {
+        timer_id: "reminder".to_string(),
+    };
+    workflow.process_event(&timer).unwrap();
+
+    let moves: Vec<_> = workflow
+        .journal()
+        .iter()
+        .map(|t| (t.node_id.as_str(), t.from, t.to, t.gate.is_some()))
+        .collect();
+    assert_eq!(
+        moves,
+        vec![
+            ("end", NodeStatus::NotStarted, NodeStatus::Active, true),
+            ("wait", NodeStatus::Active, NodeStatus::Completed, false),
+            ("end", NodeStatus::Active, NodeStatus::Completed, false),
+        ]
+    );
+    assert!(workflow.journal().iter().all(|t| t.event == timer));
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FailingBehavior(String);
+
+#[typetag::serde]
+impl NodeBehavior for FailingBehavior {
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        Err(BehaviorError::new(self.0.clone()))
+    }
+
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
+}
+
+/// start -> charge -> end, where charging fails under `policy`. A separate
+/// refund node is only reachable through charge's error edge.
+fn failing_workflow(policy: FailurePolicy) -> Workflow {
+    let mut charge = plain_node(
+        "charge",
+        NodeStatus::NotStarted,
+        vec![edge_to("end", Gate::Single(Box::new(TestCondition(true))))],
+    );
+    charge.behavior = Box::new(FailingBehavior("card declined".to_string()));
+    charge.on_failure = policy;
+
+    Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![edge_to(
+                "charge",
+                Gate::Single(Box::new(UserActivityCondition)),
+            )],
+        ),
+        charge,
+        plain_node("end", NodeStatus::NotStarted, vec![]),
+        plain_node("refund", NodeStatus::NotStarted, vec![]),
+    ])
+}
+
+fn status_of(workflow: &Workflow, id: &str) -> NodeStatus {
+    workflow.node(&NodeId::from(id)).unwrap().status
+}
+
+#[test]
+fn test_behavior_failure_fails_workflow() {
+    let mut workflow = failing_workflow(FailurePolicy::FailWorkflow);
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    assert_eq!(workflow.status, WorkflowStatus::Failed);
+    assert_eq!(status_of(&workflow, "charge"), NodeStatus::Failed);
+    let failure = workflow.failure.as_ref().unwrap();
+    assert_eq!(failure.node_id, NodeId::from("charge"));
+    assert_eq!(failure.reason, "card declined");
+    let failed = workflow.journal().last().unwrap();
+    assert_eq!(failed.to, NodeStatus::Failed);
+    assert_eq!(failed.error.as_deref(), Some("card declined"));
+
+    // A failed workflow no longer reacts to events
+    workflow.take_journal();
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert!(workflow.journal().is_empty());
+}
+
+#[test]
+fn test_behavior_failure_policies() {
+    let mut workflow = failing_workflow(FailurePolicy::Continue);
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+    assert_eq!(status_of(&workflow, "charge"), NodeStatus::Failed);
+    assert_eq!(status_of(&workflow, "end"), NodeStatus::NotStarted);
+    assert_eq!(status_of(&workflow, "refund"), NodeStatus::NotStarted);
+    // Completed, but the failure it carried on past is kept
+    let failure = workflow.failure.as_ref().unwrap();
+    assert_eq!(failure.node_id, NodeId::from("charge"));
+    assert_eq!(failure.reason, "card declined");
+
+    let mut workflow = failing_workflow(FailurePolicy::ErrorEdge(NodeId::from("refund")));
+    assert_eq!(workflow.validate(), Ok(()));
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+    assert_eq!(status_of(&workflow, "end"), NodeStatus::NotStarted);
+    assert_eq!(status_of(&workflow, "charge"), NodeStatus::Failed);
+    assert_eq!(status_of(&workflow, "refund"), NodeStatus::Completed);
+    assert!(workflow.failure.is_none());
+
+    let workflow = failing_workflow(FailurePolicy::ErrorEdge(NodeId::from("missing")));
+    assert_eq!(
+        workflow.validate(),
+        Err(vec![
+            ValidationError::DanglingErrorEdge {
+                node: NodeId::from("charge"),
+                target: NodeId::from("missing"),
+            },
+            // Only the error edge led to refund
+            ValidationError::UnreachableNode(NodeId::from("refund")),
+        ])
+    );
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FlakyBehavior {
+    failures_left: std::sync::atomic::AtomicUsize,
+}
+
+#[typetag::serde]
+impl NodeBehavior for FlakyBehavior {
+    fn on_activated(
+        &self,
+        _context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        let left = self.failures_left.load(std::sync::atomic::Ordering::SeqCst);
+        if left > 0 {
+            self.failures_left
+                .store(left - 1, std::sync::atomic::Ordering::SeqCst);
+            return Err(BehaviorError::new("gateway timeout"));
+        }
+        Ok(None)
+    }
+
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
+}
+
+fn retry_timer(id: &str) -> Event {
+    Event::Timer {
+        timer_id: id.to_string(),
+    }
+}
+
+#[test]
+fn test_retry_backoff() {
+    let policy = RetryPolicy::new(5, std::time::Duration::from_secs(1))
+        .with_max_backoff(std::time::Duration::from_secs(3))
+        .with_jitter(0.0);
+    let delays: Vec<_> = (1..=4)
+        .map(|n| policy.backoff(n, "charge").as_secs())
+        .collect();
+    assert_eq!(delays, vec![1, 2, 3, 3]);
+    assert!(policy.allows_retry(4));
+    assert!(!policy.allows_retry(5));
+
+    let jittered = RetryPolicy::new(5, std::time::Duration::from_secs(10)).with_jitter(0.5);
+    let delays: Vec<_> = (0..100)
+        .map(|i| jittered.backoff(1, &format!("charge:{}", i)))
+        .collect();
+    assert!(delays
+        .iter()
+        .all(|delay| (5.0..=15.0).contains(&delay.as_secs_f64())));
+    assert!(delays.iter().any(|delay| *delay != delays[0]));
+    // The same retry always waits as long
+    assert_eq!(jittered.backoff(1, "charge:0"), delays[0]);
+}
+
+#[test]
+fn test_retry_backoff_is_capped_for_huge_attempts() {
+    let max = std::time::Duration::from_secs(600);
+    let policy = RetryPolicy::new(u32::MAX, std::time::Duration::from_secs(1))
+        .with_max_backoff(max)
+        .with_multiplier(1e300);
+    assert_eq!(
+        policy.clone().with_jitter(0.0).backoff(u32::MAX, "charge"),
+        max
+    );
+    assert!(policy.backoff(u32::MAX, "charge") <= max);
+    assert!(policy.with_multiplier(f64::NAN).backoff(3, "charge") <= max);
+
+    // Jitter on top of a cap beyond what a `Duration` holds
+    let unbounded = RetryPolicy::new(u32::MAX, std::time::Duration::from_secs(1))
+        .with_max_backoff(std::time::Duration::MAX)
+        .with_multiplier(1e300)
+        .with_jitter(1.0);
+    for i in 0..20 {
+        assert!(unbounded.backoff(u32::MAX, &format!("charge:{}", i)) <= std::time::Duration::MAX);
+    }
+}
+
+#[test]
+fn test_retry_survives_restart_and_succeeds() {
+    let mut workflow = failing_workflow(FailurePolicy::FailWorkflow);
+    let charge = workflow.node_mut(&NodeId::from("charge")).unwrap();
+    charge.behavior = Box::new(FlakyBehavior {
+        failures_left: std::sync::atomic::AtomicUsize::new(2),
+    });
+    charge.retry = Some(RetryPolicy::new(3, std::time::Duration::from_secs(1)));
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(status_of(&workflow, "charge"), NodeStatus::Active);
+    assert_eq!(workflow.scheduled_timers[0].timer_id, "retry:charge:1");
+
+    // Attempt counts are part of the stored workflow
+    let mut workflow = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+    let state = &workflow.retries[&NodeId::from("charge")];
+    assert_eq!(state.attempts, 1);
+    assert_eq!(state.last_error, "gateway timeout");
+
+    // Unrelated events do not move a node that is waiting to retry
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(status_of(&workflow, "end"), NodeStatus::NotStarted);
+
+    workflow
+        .process_event(&retry_timer("retry:charge:1"))
+        .unwrap();
+    assert_eq!(workflow.retries[&NodeId::from("charge")].attempts, 2);
+
+    workflow
+        .process_event(&retry_timer("retry:charge:2"))
+        .unwrap();
+    assert!(workflow.retries.is_empty());
+    assert_eq!(status_of(&workflow, "charge"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "end"), NodeStatus::Completed);
+}
+
+#[test]
+fn test_exhausted_retries_fail_node() {
+    let mut workflow = failing_workflow(FailurePolicy::FailWorkflow);
+    workflow.node_mut(&NodeId::from("charge")).unwrap().retry =
+        Some(RetryPolicy::new(2, std::time::Duration::from_secs(1)));
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(workflow.status, WorkflowStatus::Active);
+
+    workflow
+        .process_event(&retry_timer("retry:charge:1"))
+        .unwrap();
+    assert_eq!(status_of(&workflow, "charge"), NodeStatus::Failed);
+    assert_eq!(workflow.status, WorkflowStatus::Failed);
+    assert!(workflow.retries.is_empty());
+    assert!(workflow.scheduled_timers.is_empty());
+}
+
+/// Emits a receipt every time it runs, but fails the first `failures_left`
+/// times.
+#[derive(Debug, Serialize, Deserialize)]
+struct ReceiptBehavior {
+    failures_left: std::sync::atomic::AtomicUsize,
+}
+
+#[typetag::serde]
+impl NodeBehavior for ReceiptBehavior {
+    fn on_activated(
+        &self,
+        context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        context.emit("receipt", serde_json::json!({ "amount": 10 }));
+        let left = self.failures_left.load(std::sync::atomic::Ordering::SeqCst);
+        if left > 0 {
+            self.failures_left
+                .store(left - 1, std::sync::atomic::Ordering::SeqCst);
+            return Err(BehaviorError::new("gateway timeout"));
+        }
+        Ok(None)
+    }
+
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_outbox_only_keeps_messages_of_successful_hooks() {
+    let mut workflow = failing_workflow(FailurePolicy::FailWorkflow);
+    let charge = workflow.node_mut(&NodeId::from("charge")).unwrap();
+    charge.behavior = Box::new(ReceiptBehavior {
+        failures_left: std::sync::atomic::AtomicUsize::new(1),
+    });
+    charge.retry = Some(RetryPolicy::new(2, std::time::Duration::from_secs(1)));
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert!(workflow.take_outbox().is_empty());
+
+    workflow
+        .process_event(&retry_timer("retry:charge:1"))
+        .unwrap();
+    assert_eq!(
+        workflow.take_outbox(),
+        vec![OutboxMessage {
+            node_id: NodeId::from("charge"),
+            topic: "receipt".to_string(),
+            payload: serde_json::json!({ "amount": 10 }),
+        }]
+    );
+    assert!(workflow.take_outbox().is_empty());
+}
+
+/// Adds `amount` to the `cart_value` variable, failing instead once the
+/// total would exceed `limit`.
+#[derive(Debug, Serialize, Deserialize)]
+struct AddToCartBehavior {
+    amount: u64,
+    limit: u64,
+}
+
+#[typetag::serde]
+impl NodeBehavior for AddToCartBehavior {
+    fn on_activated(
+        &self,
+        context: &mut NodeContext,
+    ) -> Result<Option<TimerRequest>, BehaviorError> {
+        let variables = context.variables_mut();
+        let total = variables
+            .get::<u64>("cart_value")
+            .map_err(|e| BehaviorError::new(e.to_string()))?
+            .unwrap_or(0)
+            + self.amount;
+        variables
+            .set("cart_value", total)
+            .map_err(|e| BehaviorError::new(e.to_string()))?;
+        if total > self.limit {
+            return Err(BehaviorError::new("cart limit exceeded"));
+        }
+        Ok(None)
+    }
+
+    fn on_completed(&self, _context: &mut NodeContext) -> Result<(), BehaviorError> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_variables_written_by_behaviors_and_read_by_gates() {
+    let mut add = plain_node(
+        "add",
+        NodeStatus::NotStarted,
+        vec![
+            edge_to(
+                "big_spender",
+                Gate::Single(Box::new(VariableEquals {
+                    key: "cart_value".to_string(),
+                    value: serde_json::json!(150),
+                })),
+            ),
+            edge_to("end", Gate::Single(Box::new(UserActivityCondition))),
+        ],
+    );
+    add.behavior = Box::new(AddToCartBehavior {
+        amount: 100,
+        limit: 1000,
+    });
+    let mut workflow = Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![edge_to(
+                "add",
+                Gate::Single(Box::new(UserActivityCondition)),
+            )],
+        ),
+        add,
+        plain_node("big_spender", NodeStatus::NotStarted, vec![]),
+        plain_node("end", NodeStatus::NotStarted, vec![]),
+    ]);
+    workflow.variables.set("cart_value", 50).unwrap();
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(
+        workflow.variables.get::<u64>("cart_value").unwrap(),
+        Some(150)
+    );
+    assert_eq!(status_of(&workflow, "big_spender"), NodeStatus::Completed);
+    assert!(workflow.variables.get::<String>("cart_value").is_err());
+
+    // Variables are part of the stored workflow
+    let restored = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+    assert_eq!(restored.variables, workflow.variables);
+}
+
+#[test]
+fn test_failed_hook_discards_variable_writes() {
+    let mut workflow = failing_workflow(FailurePolicy::Continue);
+    workflow.node_mut(&NodeId::from("charge")).unwrap().behavior = Box::new(AddToCartBehavior {
+        amount: 100,
+        limit: 50,
+    });
+
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(status_of(&workflow, "charge"), NodeStatus::Failed);
+    assert!(workflow.variables.is_empty());
+}
+
+/// start -> wait, where wait moves to `bought` on a purchase within 48 hours
+/// of activating and to `timed_out` once those 48 hours have passed.
+fn deadline_workflow() -> Workflow {
+    let window = std::time::Duration::from_secs(48 * 3600);
+    Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![edge_to(
+                "wait",
+                Gate::Single(Box::new(UserActivityCondition)),
+            )],
+        ),
+        plain_node(
+            "wait",
+            NodeStatus::NotStarted,
+            vec![
+                edge_to(
+                    "bought",
+                    Gate::And(vec![
+                        Gate::Single(Box::new(CustomEventCondition::new("purchase"))),
+                        Gate::WithinWindow(window),
+                    ]),
+                ),
+                edge_to("timed_out", Gate::DeadlineElapsed(window)),
+            ],
+        ),
+        plain_node("bought", NodeStatus::NotStarted, vec![]),
+        plain_node("timed_out", NodeStatus::NotStarted, vec![]),
+    ])
+}
+
+fn purchase() -> Event {
+    Event::Custom {
+        event_type: "purchase".to_string(),
+        payload: serde_json::json!({}),
+    }
+}
+
+#[test]
+fn test_deadline_schedules_timer_and_records_timestamps() {
+    let start = time::OffsetDateTime::now_utc();
+    let hours = |h: i64| start + time::Duration::hours(h);
+
+    let mut workflow = deadline_workflow();
+    workflow
+        .process_event_at(&Event::UserActivity, start)
+        .unwrap();
+    let wait = workflow.node(&NodeId::from("wait")).unwrap();
+    assert_eq!(wait.activated_at, Some(start));
+    assert_eq!(
+        workflow.node(&NodeId::from("start")).unwrap().completed_at,
+        Some(start)
+    );
+    assert_eq!(
+        workflow.scheduled_timers[0].timer_id,
+        "deadline:wait:172800000ms"
+    );
+    assert_eq!(workflow.scheduled_timers[0].fire_at, hours(48));
+
+    workflow.process_event_at(&purchase(), hours(47)).unwrap();
+    assert_eq!(status_of(&workflow, "bought"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "timed_out"), NodeStatus::NotStarted);
+}
+
+#[test]
+fn test_deadline_elapsed_takes_timeout_edge() {
+    let start = time::OffsetDateTime::now_utc();
+    let hours = |h: i64| start + time::Duration::hours(h);
+
+    let mut workflow = deadline_workflow();
+    workflow
+        .process_event_at(&Event::UserActivity, start)
+        .unwrap();
+
+    // A purchase after the window takes the timeout edge instead
+    workflow.process_event_at(&purchase(), hours(49)).unwrap();
+    assert_eq!(status_of(&workflow, "bought"), NodeStatus::NotStarted);
+    assert_eq!(status_of(&workflow, "timed_out"), NodeStatus::Completed);
+
+    // Without any other event the deadline timer does the same
+
+    let mut workflow = deadline_workflow();
+    workflow
+        .process_event_at(&Event::UserActivity, start)
+        .unwrap();
+    workflow
+        .process_event_at(&retry_timer("deadline:wait:172800000ms"), hours(48))
+        .unwrap();
+    assert_eq!(status_of(&workflow, "timed_out"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "bought"), NodeStatus::NotStarted);
+}
+
+#[test]
+fn test_deadline_on_initial_node_counts_from_creation() {
+    let day = std::time::Duration::from_secs(24 * 3600);
+    let workflow = Workflow::new(vec![
+        plain_node(
+            "signed_up",
+            NodeStatus::Active,
+            vec![edge_to("remind", Gate::DeadlineElapsed(day))],
+        ),
+        plain_node("remind", NodeStatus::NotStarted, vec![]),
+    ]);
+    let created = workflow.nodes[0].activated_at.unwrap();
+    assert_eq!(workflow.scheduled_timers.len(), 1);
+    assert_eq!(workflow.scheduled_timers[0].fire_at, created + day);
+
+    let mut workflow = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+    workflow
+        .process_event_at(&Event::UserActivity, created + day / 2)
+        .unwrap();
+    assert_eq!(status_of(&workflow, "remind"), NodeStatus::NotStarted);
+
+    let timer_id = deadline_timer_id(&NodeId::from("signed_up"), day);
+    workflow
+        .process_event_at(&Event::Timer { timer_id }, created + day)
+        .unwrap();
+    assert_eq!(status_of(&workflow, "remind"), NodeStatus::Completed);
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_cancel_stops_workflow_and_its_timers() {
+    let mut workflow = create_timer_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+    workflow.take_timer_commands();
+    workflow.take_journal();
+
+    workflow.cancel("user unsubscribed");
+    assert_eq!(workflow.status, WorkflowStatus::Cancelled);
+    assert_eq!(workflow.cancel_reason.as_deref(), Some("user unsubscribed"));
+    assert_eq!(status_of(&workflow, "wait"), NodeStatus::Cancelled);
+    assert_eq!(status_of(&workflow, "end"), NodeStatus::NotStarted);
+    assert!(workflow.scheduled_timers.is_empty());
+    assert_eq!(
+        workflow.take_timer_commands(),
+        vec![TimerCommand::Cancel {
+            node_id: NodeId::from("wait")
+        }]
+    );
+    let cancelled = workflow.take_journal();
+    assert_eq!(cancelled.len(), 1);
+    assert_eq!(cancelled[0].error.as_deref(), Some("user unsubscribed"));
+
+    // A cancelled workflow no longer reacts to events
+    workflow
+        .process_event(&Event::Timer {
+            timer_id: "reminder".to_string(),
+        })
+        .unwrap();
+    assert_eq!(status_of(&workflow, "end"), NodeStatus::NotStarted);
+    assert!(workflow.journal().is_empty());
+
+    let restored = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+    assert_eq!(restored.status, WorkflowStatus::Cancelled);
+}
+
+#[test]
+fn test_cancel_ignores_finished_workflows() {
+    let mut workflow = failing_workflow(FailurePolicy::FailWorkflow);
+    workflow.process_event(&Event::UserActivity).unwrap();
+    workflow.take_journal();
+
+    workflow.cancel("too late");
+    assert_eq!(workflow.status, WorkflowStatus::Failed);
+    assert!(workflow.cancel_reason.is_none());
+    assert!(workflow.journal().is_empty());
+}
+
+/// Emits an `undo` message naming what it undid, failing the first
+/// `failures_left` times.
+#[derive(Debug, Serialize, Deserialize)]
+struct UndoCompensation {
+    what: String,
+    failures_left: std::sync::atomic::AtomicUsize,
+}
+
+#[typetag::serde]
+impl Compensation for UndoCompensation {
+    fn compensate(&self, context: &mut NodeContext) -> Result<(), BehaviorError> {
+        let left = self.failures_left.load(std::sync::atomic::Ordering::SeqCst);
+        if left > 0 {
+            self.failures_left
+                .store(left - 1, std::sync::atomic::Ordering::SeqCst);
+            return Err(BehaviorError::new("inventory unavailable"));
+        }
+        context.emit("undo", serde_json::json!(self.what));
+        Ok(())
+    }
+}
+
+fn undo(what: &str, failures: usize) -> Option<Box<dyn Compensation>> {
+    Some(Box::new(UndoCompensation {
+        what: what.to_string(),
+        failures_left: std::sync::atomic::AtomicUsize::new(failures),
+    }))
+}
+
+/// start -> coupon -> reserve -> charge, where charging always fails and
+/// the coupon and reservation have compensations.
+fn saga_workflow(reserve_failures: usize) -> Workflow {
+    let pass = || Gate::Single(Box::new(TestCondition(true)));
+    let mut coupon = plain_node(
+        "coupon",
+        NodeStatus::NotStarted,
+        vec![edge_to("reserve", pass())],
+    );
+    coupon.compensation = undo("coupon", 0);
+    let mut reserve = plain_node(
+        "reserve",
+        NodeStatus::NotStarted,
+        vec![edge_to("charge", pass())],
+    );
+    reserve.compensation = undo("reserve", reserve_failures);
+    reserve.retry = Some(RetryPolicy::new(2, std::time::Duration::from_secs(1)));
+    let mut charge = plain_node("charge", NodeStatus::NotStarted, vec![]);
+    charge.behavior = Box::new(FailingBehavior("card declined".to_string()));
+
+    Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![edge_to(
+                "coupon",
+                Gate::Single(Box::new(UserActivityCondition)),
+            )],
+        ),
+        coupon,
+        reserve,
+        charge,
+    ])
+}
+
+fn undone(workflow: &mut Workflow) -> Vec<serde_json::Value> {
+    workflow
+        .take_outbox()
+        .into_iter()
+        .map(|message| message.payload)
+        .collect()
+}
+
+#[test]
+fn test_failure_compensates_in_reverse_completion_order() {
+    let mut workflow = saga_workflow(0);
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    assert_eq!(workflow.status, WorkflowStatus::Failed);
+    assert_eq!(undone(&mut workflow), vec!["reserve", "coupon"]);
+    assert_eq!(status_of(&workflow, "reserve"), NodeStatus::Compensated);
+    assert_eq!(status_of(&workflow, "coupon"), NodeStatus::Compensated);
+    assert_eq!(status_of(&workflow, "start"), NodeStatus::Completed);
+    assert!(!workflow.is_compensating());
+    assert_eq!(
+        workflow.compensation.as_ref().unwrap().compensated,
+        vec![NodeId::from("reserve"), NodeId::from("coupon")]
+    );
+}
+
+#[test]
+fn test_failed_compensation_retries_after_restart() {
+    let mut workflow = saga_workflow(1);
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert!(workflow.is_compensating());
+    assert!(undone(&mut workflow).is_empty());
+
+    // The remaining compensations wait for the retry, even across a restart
+    let mut workflow = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+    let state = workflow.compensation.clone().unwrap();
+    assert_eq!(
+        state.pending,
+        vec![NodeId::from("reserve"), NodeId::from("coupon")]
+    );
+    assert_eq!(state.timer_id.as_deref(), Some("compensate:reserve:1"));
+
+    workflow
+        .process_event(&retry_timer("compensate:reserve:1"))
+        .unwrap();
+    assert_eq!(undone(&mut workflow), vec!["reserve", "coupon"]);
+    assert!(!workflow.is_compensating());
+    assert!(workflow.scheduled_timers.is_empty());
+}
+
+#[test]
+fn test_cancel_compensates_and_keeps_compensation_messages() {
+    let mut workflow = saga_workflow(0);
+    let reserve = workflow.node_mut(&NodeId::from("reserve")).unwrap();
+    reserve.edges = vec![edge_to(
+        "charge",
+        Gate::Single(Box::new(TestCondition(false))),
+    )];
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(workflow.status, WorkflowStatus::Active);
+    assert!(!workflow.take_outbox_cancellation());
+
+    workflow.cancel("user unsubscribed");
+    assert!(workflow.take_outbox_cancellation());
+    // Reserve was still active, so only the coupon is compensated
+    assert_eq!(undone(&mut workflow), vec!["coupon"]);
+    assert_eq!(status_of(&workflow, "reserve"), NodeStatus::Cancelled);
+}
+
+/// start -> onboarding -> done, where onboarding runs the `onboarding`
+/// definition as a child workflow.
+fn parent_workflow() -> Workflow {
+    let mut onboarding = plain_node(
+        "onboarding",
+        NodeStatus::NotStarted,
+        vec![edge_to(
+            "done",
+            Gate::Single(Box::new(SubWorkflowCompleted {
+                node_id: NodeId::from("onboarding"),
+            })),
+        )],
+    );
+    onboarding.sub_workflow = Some(SubWorkflow::new("onboarding"));
+
+    Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![edge_to(
+                "onboarding",
+                Gate::Single(Box::new(UserActivityCondition)),
+            )],
+        ),
+        onboarding,
+        plain_node("done", NodeStatus::NotStarted, vec![]),
+    ])
+}
+
+fn child_finished(workflow: &Workflow, status: WorkflowStatus, reason: Option<&str>) -> Event {
+    Event::ChildFinished {
+        node_id: NodeId::from("onboarding"),
+        child_id: workflow.children[&NodeId::from("onboarding")],
+        status,
+        reason: reason.map(str::to_string),
+    }
+}
+
+#[test]
+fn test_sub_workflow_node_waits_for_child() {
+    let mut workflow = parent_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+
+    let requests = workflow.take_child_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].node_id, NodeId::from("onboarding"));
+    assert_eq!(requests[0].definition, "onboarding");
+    assert_eq!(
+        workflow.children[&NodeId::from("onboarding")],
+        requests[0].child_id
+    );
+    assert_eq!(status_of(&workflow, "onboarding"), NodeStatus::Active);
+
+    // The node holds even on events its edges would pass on, across a restart
+    let mut workflow = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+    workflow.nodes[1].edges = vec![edge_to("done", Gate::Single(Box::new(TestCondition(true))))];
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(status_of(&workflow, "onboarding"), NodeStatus::Active);
+
+    let event = child_finished(&workflow, WorkflowStatus::Completed, None);
+    workflow.process_event(&event).unwrap();
+    assert!(workflow.children.is_empty());
+    assert_eq!(status_of(&workflow, "onboarding"), NodeStatus::Completed);
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_failed_child_fails_sub_workflow_node() {
+    let mut workflow = parent_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+    let child_id = workflow.children[&NodeId::from("onboarding")];
+
+    let event = child_finished(&workflow, WorkflowStatus::Failed, Some("bounced"));
+    workflow.process_event(&event).unwrap();
+    assert_eq!(status_of(&workflow, "onboarding"), NodeStatus::Failed);
+    assert_eq!(workflow.status, WorkflowStatus::Failed);
+    assert_eq!(
+        workflow.failure.as_ref().unwrap().reason,
+        format!("sub-workflow {} failed: bounced", child_id)
+    );
+}
+
+#[test]
+fn test_finished_child_notifies_parent() {
+    let parent_id = uuid::Uuid::new_v4();
+    let mut child = failing_workflow(FailurePolicy::FailWorkflow);
+    child.parent = Some(ParentLink {
+        workflow_id: parent_id,
+        node_id: NodeId::from("onboarding"),
+    });
+    assert!(child.take_parent_notification().is_none());
+
+    child.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(child.status, WorkflowStatus::Failed);
+    match child.take_parent_notification() {
+        Some(Event::ChildFinished {
+            node_id,
+            child_id,
+            status,
+            reason,
+        }) => {
+            assert_eq!(node_id, NodeId::from("onboarding"));
+            assert_eq!(child_id, child.id);
+            assert_eq!(status, WorkflowStatus::Failed);
+            assert_eq!(reason, child.failure.as_ref().map(|f| f.to_string()));
+        }
+        other => panic!("unexpected notification: {:?}", other),
+    }
+}
+
+#[test]
+fn test_cancel_cancels_running_children() {
+    let mut workflow = parent_workflow();
+    workflow.process_event(&Event::UserActivity).unwrap();
+    let child_id = workflow.children[&NodeId::from("onboarding")];
+    assert!(workflow.take_child_cancellations().is_empty());
+
+    workflow.cancel("user unsubscribed");
+    assert_eq!(workflow.take_child_cancellations(), vec![child_id]);
+    assert!(workflow.children.is_empty());
+    // A parent without a parent of its own has no one to tell
+    assert!(workflow.take_parent_notification().is_none());
+}
+
+#[test]
+fn test_initial_sub_workflow_node_requests_its_child() {
+    let mut onboarding = plain_node(
+        "onboarding",
+        NodeStatus::Active,
+        vec![edge_to(
+            "done",
+            Gate::Single(Box::new(SubWorkflowCompleted {
+                node_id: NodeId::from("onboarding"),
+            })),
+        )],
+    );
+    onboarding.sub_workflow = Some(SubWorkflow::new("onboarding"));
+    let mut workflow = Workflow::new(vec![
+        onboarding,
+        plain_node("done", NodeStatus::NotStarted, vec![]),
+    ]);
+
+    let requests = workflow.take_child_requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].definition, "onboarding");
+    let child_id = workflow.children[&NodeId::from("onboarding")];
+    assert_eq!(requests[0].child_id, child_id);
+
+    workflow
+        .process_event(&Event::ChildFinished {
+            node_id: NodeId::from("onboarding"),
+            child_id,
+            status: WorkflowStatus::Completed,
+            reason: None,
+        })
+        .unwrap();
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_loop_back_over_sub_workflow_ignores_cancelled_child() {
+    // start starts onboarding and remind together, and remind starts
+    // onboarding over on each tick while its child is still running
+    let mut onboarding = plain_node(
+        "onboarding",
+        NodeStatus::NotStarted,
+        vec![edge_to(
+            "remind",
+            Gate::Single(Box::new(SubWorkflowCompleted {
+                node_id: NodeId::from("onboarding"),
+            })),
+        )],
+    );
+    onboarding.sub_workflow = Some(SubWorkflow::new("onboarding"));
+    let mut remind = plain_node(
+        "remind",
+        NodeStatus::NotStarted,
+        vec![edge_to(
+            "done",
+            Gate::Single(Box::new(CustomEventCondition::new("purchase"))),
+        )],
+    );
+    remind.loop_back = Some(LoopBack {
+        target: NodeId::from("onboarding"),
+        gate: Gate::Single(Box::new(CustomEventCondition::new("tick"))),
+        max_iterations: 3,
+        exit: NodeId::from("give_up"),
+    });
+    let mut workflow = Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![
+                edge_to("onboarding", Gate::Single(Box::new(UserActivityCondition))),
+                edge_to("remind", Gate::Single(Box::new(UserActivityCondition))),
+            ],
+        ),
+        onboarding,
+        remind,
+        plain_node("done", NodeStatus::NotStarted, vec![]),
+        plain_node("give_up", NodeStatus::NotStarted, vec![]),
+    ]);
+    assert_eq!(workflow.validate(), Ok(()));
+    workflow.process_event(&Event::UserActivity).unwrap();
+    let first = workflow.children[&NodeId::from("onboarding")];
+
+    workflow.process_event(&tick()).unwrap();
+    assert_eq!(workflow.take_child_cancellations(), vec![first]);
+    let second = workflow.children[&NodeId::from("onboarding")];
+    assert_ne!(first, second);
+
+    // The cancelled child reports back, but the node waits for its new one
+    workflow
+        .process_event(&Event::ChildFinished {
+            node_id: NodeId::from("onboarding"),
+            child_id: first,
+            status: WorkflowStatus::Cancelled,
+            reason: Some("parent workflow cancelled".to_string()),
+        })
+        .unwrap();
+    assert_eq!(status_of(&workflow, "onboarding"), NodeStatus::Active);
+    assert_eq!(workflow.status, WorkflowStatus::Active);
+
+    let event = child_finished(&workflow, WorkflowStatus::Completed, None);
+    workflow.process_event(&event).unwrap();
+    assert_eq!(status_of(&workflow, "onboarding"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "remind"), NodeStatus::Active);
+}
+
+/// start fans out to merge, whose edge to done waits on `join`, and to the
+/// email, sms and push branches, each of which completes on its own custom
+/// event.
+fn fan_out_workflow(join: Join) -> Workflow {
+    let pass = || Gate::Single(Box::new(TestCondition(true)));
+    let branch = |id: &str| {
+        plain_node(
+            id,
+            NodeStatus::NotStarted,
+            vec![edge_to(
+                &format!("{}_sent", id),
+                Gate::Single(Box::new(CustomEventCondition::new(id.to_string()))),
+            )],
+        )
+    };
+    Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![
+                edge_to("merge", pass()),
+                edge_to("email", pass()),
+                edge_to("sms", pass()),
+                edge_to("push", pass()),
+            ],
+        ),
+        plain_node(
+            "merge",
+            NodeStatus::NotStarted,
+            vec![edge_to("done", Gate::Join(join))],
+        ),
+        plain_node("done", NodeStatus::NotStarted, vec![]),
+        branch("email"),
+        branch("sms"),
+        branch("push"),
+        plain_node("email_sent", NodeStatus::NotStarted, vec![]),
+        plain_node("sms_sent", NodeStatus::NotStarted, vec![]),
+        plain_node("push_sent", NodeStatus::NotStarted, vec![]),
+    ])
+}
+
+fn branches() -> Vec<NodeId> {
+    vec![
+        NodeId::from("email_sent"),
+        NodeId::from("sms_sent"),
+        NodeId::from("push_sent"),
+    ]
+}
+
+fn arrive(workflow: &mut Workflow, branch: &str) {
+    workflow
+        .process_event(&Event::Custom {
+            event_type: branch.to_string(),
+            payload: serde_json::Value::Null,
+        })
+        .unwrap();
+}
+
+#[test]
+fn test_quorum_join() {
+    let mut workflow = fan_out_workflow(Join::at_least(2, branches()));
+    assert_eq!(workflow.validate(), Ok(()));
+
+    arrive(&mut workflow, "email");
+    assert_eq!(status_of(&workflow, "email_sent"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "done"), NodeStatus::NotStarted);
+
+    // Merge was evaluated before the second branch completed, and still
+    // passes on the same event
+    arrive(&mut workflow, "push");
+    assert_eq!(status_of(&workflow, "merge"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "done"), NodeStatus::Completed);
+
+    arrive(&mut workflow, "sms");
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_first_arrival_join_fires_once() {
+    let mut workflow = fan_out_workflow(Join::any(branches()));
+
+    arrive(&mut workflow, "sms");
+    assert_eq!(status_of(&workflow, "done"), NodeStatus::Completed);
+    let fired = workflow.take_journal();
+
+    // Later branches finish without running the join again
+    arrive(&mut workflow, "email");
+    arrive(&mut workflow, "push");
+    let later: Vec<_> = workflow
+        .take_journal()
+        .into_iter()
+        .map(|t| t.node_id)
+        .filter(|id| *id == NodeId::from("merge") || *id == NodeId::from("done"))
+        .collect();
+    assert!(later.is_empty());
+    assert!(fired.iter().any(|t| t.node_id == NodeId::from("done")));
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+}
+
+#[test]
+fn test_join_counts_failed_branches() {
+    let mut workflow = fan_out_workflow(
+        Join::all(branches()).with_statuses(vec![NodeStatus::Completed, NodeStatus::Failed]),
+    );
+    let email = workflow.node_mut(&NodeId::from("email_sent")).unwrap();
+    email.behavior = Box::new(FailingBehavior("bounced".to_string()));
+    email.on_failure = FailurePolicy::Continue;
+
+    arrive(&mut workflow, "email");
+    arrive(&mut workflow, "sms");
+    assert_eq!(status_of(&workflow, "email_sent"), NodeStatus::Failed);
+    assert_eq!(status_of(&workflow, "done"), NodeStatus::NotStarted);
+
+    arrive(&mut workflow, "push");
+    assert_eq!(status_of(&workflow, "done"), NodeStatus::Completed);
+    assert_eq!(workflow.status, WorkflowStatus::Completed);
+
+    // Without Failed the join never passes
+    let mut workflow = fan_out_workflow(Join::all(branches()));
+    let email = workflow.node_mut(&NodeId::from("email_sent")).unwrap();
+    email.behavior = Box::new(FailingBehavior("bounced".to_string()));
+    email.on_failure = FailurePolicy::Continue;
+    for branch in ["email", "sms", "push"] {
+        arrive(&mut workflow, branch);
+    }
+    assert_eq!(status_of(&workflow, "merge"), NodeStatus::Active);
+}
+
+#[test]
+fn test_validate_join_quorum() {
+    for quorum in [0, 4] {
+        let workflow = fan_out_workflow(Join::at_least(quorum, branches()));
+        assert_eq!(
+            workflow.validate(),
+            Err(vec![ValidationError::InvalidJoinQuorum {
+                node: NodeId::from("merge"),
+                quorum,
+                nodes: 3,
+            }])
+        );
+    }
+}
+
+/// start -> nudge -> wait, where wait goes on to converted on a purchase
+/// and otherwise loops back to nudge on `again`, at most three nudges in
+/// all before giving up.
+fn nudge_workflow(again: Gate) -> Workflow {
+    let mut wait = plain_node(
+        "wait",
+        NodeStatus::NotStarted,
+        vec![edge_to(
+            "converted",
+            Gate::Single(Box::new(CustomEventCondition::new("purchase"))),
+        )],
+    );
+    wait.loop_back = Some(LoopBack {
+        target: NodeId::from("nudge"),
+        gate: again,
+        max_iterations: 3,
+        exit: NodeId::from("give_up"),
+    });
+
+    Workflow::new(vec![
+        plain_node(
+            "start",
+            NodeStatus::Active,
+            vec![edge_to(
+                "nudge",
+                Gate::Single(Box::new(UserActivityCondition)),
+            )],
+        ),
+        plain_node(
+            "nudge",
+            NodeStatus::NotStarted,
+            vec![edge_to("wait", Gate::Single(Box::new(TestCondition(true))))],
+        ),
+        wait,
+        plain_node("converted", NodeStatus::NotStarted, vec![]),
+        plain_node("give_up", NodeStatus::NotStarted, vec![]),
+    ])
+}
+
+fn tick() -> Event {
+    Event::Custom {
+        event_type: "tick".to_string(),
+        payload: serde_json::Value::Null,
+    }
+}
+
+fn nudges(workflow: &mut Workflow) -> usize {
+    workflow
+        .take_journal()
+        .iter()
+        .filter(|t| t.node_id == NodeId::from("nudge") && t.to == NodeStatus::Active)
+        .count()
+}
+
+#[test]
+fn test_loop_back_until_max_iterations() {
+    let mut workflow = nudge_workflow(Gate::Single(Box::new(CustomEventCondition::new("tick"))));
+    assert_eq!(workflow.validate(), Ok(()));
+    workflow.process_event(&Event::UserActivity).unwrap();
+    assert_eq!(nudges(&mut workflow), 1);
+
+    // Each tick loops once, even though wait is activated again on it
+    workflow.process_event(&tick()).unwrap();
+    assert_eq!(nudges(&mut workflow), 1);
+    assert_eq!(status_of(&workflow, "wait"), NodeStatus::Active);
+    assert_eq!(workflow.iterations[&NodeId::from("nudge")], 1);
+
+    let mut workflow = Workflow::from_bytes(&workflow.to_bytes().unwrap()).unwrap();
+    workflow.process_event(&tick()).unwrap();
+    assert_eq!(nudges(&mut workflow), 1);
+    assert_eq!(workflow.iterations[&NodeId::from("nudge")], 2);
+
+    // The third nudge was the last, so the next tick takes the exit
+    workflow.process_event(&tick()).unwrap();
+    assert_eq!(nudges(&mut workflow), 0);
+    assert_eq!(status_of(&workflow, "wait"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "give_up"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "converted"), NodeStatus::NotStarted);
+}
+
+#[test]
+fn test_loop_resets_nodes_and_leaves_on_regular_edge() {
+    let mut workflow = nudge_workflow(Gate::Single(Box::new(CustomEventCondition::new("tick"))));
+    workflow.process_event(&Event::UserActivity).unwrap();
+    workflow.take_journal();
+    workflow.process_event(&tick()).unwrap();
+
+    let reset: Vec<_> = workflow
+        .journal()
+        .iter()
+        .filter(|t| t.to == NodeStatus::NotStarted)
+        .map(|t| t.node_id.clone())
+        .collect();
+    assert!(reset.contains(&NodeId::from("wait")));
+    assert!(reset.contains(&NodeId::from("nudge")));
+    assert_eq!(status_of(&workflow, "start"), NodeStatus::Completed);
+
+    workflow
+        .process_event(&Event::Custom {
+            event_type: "purchase".to_string(),
+            payload: serde_json::Value::Null,
+        })
+        .unwrap();
+    assert_eq!(status_of(&workflow, "converted"), NodeStatus::Completed);
+    assert_eq!(status_of(&workflow, "give_up"), NodeStatus::NotStarted);
+}
+
+#[test]
+fn test_loop_back_on_deadline_waits_for_next_timer() {
+    let every = std::time::Duration::from_secs(3 * 24 * 3600);
+    let mut workflow = nudge_workflow(Gate::DeadlineElapsed(every));
+    let start = time::OffsetDateTime::now_utc();
+    workflow
+        .process_event_at(&Event::UserActivity, start)
+        .unwrap();
+    let timer_id = deadline_timer_id(&NodeId::from("wait"), every);
+
+    workflow
+        .process_event_at(
+            &Event::Timer {
+                timer_id: timer_id.clone(),
+            },
+            start + every,
+        )
+        .unwrap();
+    assert_eq!(workflow.iterations[&NodeId::from("nudge")], 1);
+    assert_eq!(status_of(&workflow, "wait"), NodeStatus::Active);
+    let timers: Vec<_> = workflow
+        .scheduled_timers
+        .iter()
+        .filter(|t| t.timer_id == timer_id)
+        .collect();
+    assert_eq!(timers.len(), 1);
+    assert_eq!(timers[0].fire_at, start + every * 2);
+}
+
+#[test]
+fn test_validate_loop_back() {
+    let mut workflow = nudge_workflow(Gate::Single(Box::new(TestCondition(true))));
+    let loop_back = workflow.nodes[2].loop_back.as_mut().unwrap();
+    loop_back.target = NodeId::from("converted");
+    loop_back.exit = NodeId::from("missing");
+
+    let errors = workflow.validate().unwrap_err();
+    assert!(errors.contains(&ValidationError::LoopBackNotUpstream {
+        node: NodeId::from("wait"),
+        target: NodeId::from("converted"),
+    }));
+    assert!(errors.contains(&ValidationError::DanglingLoopBack {
+        node: NodeId::from("wait"),
+        target: NodeId::from("missing"),
+    }));
+    // give_up is only reachable through the exit, which is now missing
+    assert!(errors.contains(&ValidationError::UnreachableNode(NodeId::from("give_up"))));
+}